@@ -0,0 +1,56 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Compares pulling one keystream byte per `read` call, the way the
+//! original `RC4DataStream` did, with the batched `Rc4Reader` path.
+//!
+//! Run with `cargo bench`.
+
+use std::hint::black_box;
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+use rc4::{Rc4, Rc4Reader};
+
+const LEN: usize = 8 << 20;
+const ROUNDS: u32 = 5;
+
+fn per_byte(data: &[u8], out: &mut [u8]) {
+  let mut raw = Rc4::new(b"Key");
+  let mut byte = [0u8; 1];
+
+  for (o, b) in out.iter_mut().zip(data) {
+    raw.read_exact(&mut byte).unwrap();
+    *o = b ^ byte[0];
+  }
+}
+
+fn batched(data: &[u8], out: &mut [u8]) {
+  let mut reader = Rc4Reader::new(b"Key", data);
+  reader.read_exact(out).unwrap();
+}
+
+fn measure(name: &str, f: fn(&[u8], &mut [u8]), data: &[u8]) -> Duration {
+  let mut out = vec![0u8; data.len()];
+  let mut best = Duration::MAX;
+
+  for _ in 0..ROUNDS {
+    let start = Instant::now();
+    f(black_box(data), black_box(&mut out));
+    best = best.min(start.elapsed());
+  }
+
+  let mbps = data.len() as f64 / best.as_secs_f64() / (1 << 20) as f64;
+  println!("{:>10}: {:>8.2?} ({:.1} MiB/s)", name, best, mbps);
+  best
+}
+
+fn main() {
+  let data = vec![0x5au8; LEN];
+
+  let slow = measure("per-byte", per_byte, &data);
+  let fast = measure("batched", batched, &data);
+
+  println!("   speedup: {:.1}x", slow.as_secs_f64() / fast.as_secs_f64());
+}
@@ -0,0 +1,75 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Decrypts the WEP frames in a pcap capture of 802.11 traffic.
+//!
+//! Run with `cargo run --example wep_decrypt <in.pcap> <out.pcap> <key>...`
+//! where each key is 10 or 26 hex digits, optionally colon-separated,
+//! and may be prefixed with its key ID as in `2=0102030405`. Keys
+//! without an ID fill slots 0, 1, 2 and 3 in order.
+
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::process;
+
+use rc4::pcap;
+use rc4::wep::{KeySet, WepKey};
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+  let hex: Vec<u8> = hex.bytes().filter(|&b| b != b':').collect();
+  if !hex.len().is_multiple_of(2) {
+    return None;
+  }
+
+  hex
+    .chunks(2)
+    .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+    .collect()
+}
+
+fn parse_keys(args: &[String]) -> Result<KeySet, String> {
+  let mut keys = KeySet::new();
+
+  for (n, arg) in args.iter().enumerate() {
+    let (id, hex) = match arg.split_once('=') {
+      Some((id, hex)) => (id.parse().map_err(|_| format!("bad key ID in {}", arg))?, hex),
+      None => (n as u8, arg.as_str())
+    };
+
+    let secret = parse_hex(hex).ok_or_else(|| format!("bad hex key {}", hex))?;
+    let key = WepKey::new(&secret).map_err(|e| e.to_string())?;
+    keys.set(id, key).map_err(|e| e.to_string())?;
+  }
+
+  Ok(keys)
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+  if args.len() < 3 {
+    return Err("usage: wep_decrypt <in.pcap> <out.pcap> <key>...".to_string());
+  }
+
+  let keys = parse_keys(&args[2..])?;
+  let input = File::open(&args[0]).map_err(|e| format!("{}: {}", args[0], e))?;
+  let output = File::create(&args[1]).map_err(|e| format!("{}: {}", args[1], e))?;
+
+  let summary = pcap::decrypt_wep(&keys, BufReader::new(input), BufWriter::new(output))
+    .map_err(|e| e.to_string())?;
+
+  eprintln!(
+    "{} frames, {} decrypted, {} without a key, {} failed",
+    summary.frames, summary.decrypted, summary.no_key, summary.failed
+  );
+  Ok(())
+}
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+
+  if let Err(e) = run(&args) {
+    eprintln!("{}", e);
+    process::exit(1);
+  }
+}
@@ -0,0 +1,165 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The CipherSaber file format.
+//!
+//! A CipherSaber file is a 10-byte random IV followed by the message
+//! encrypted with RC4, keyed with the passphrase followed by the IV.
+//! CipherSaber-1 runs the RC4 key schedule once; CipherSaber-2 repeats
+//! it, 20 times by default.
+
+use std::io::{Read, Write};
+
+use crate::ksa::RepeatedKsa;
+use crate::{key, wipe, Error, Rc4, Rc4Reader, Rc4Writer, Result, MAX_KEY_LEN};
+
+/// The length of the IV at the start of every file.
+pub const IV_LEN: usize = 10;
+
+/// The longest passphrase that still fits in an RC4 key with the IV.
+pub const MAX_PASSPHRASE_LEN: usize = MAX_KEY_LEN - IV_LEN;
+
+/// Key schedule rounds for CipherSaber-1.
+pub const CS1_ROUNDS: usize = 1;
+
+/// The default key schedule rounds for CipherSaber-2.
+pub const CS2_ROUNDS: usize = RepeatedKsa::CIPHERSABER2_PASSES;
+
+/// Encrypts `msg` with a fresh random IV, returning the whole file.
+pub fn encrypt(passphrase: &[u8], msg: &[u8], rounds: usize) -> Result<Vec<u8>> {
+  encrypt_with_iv(passphrase, &random_iv()?, msg, rounds)
+}
+
+/// Encrypts `msg` with the given IV, returning the whole file.
+///
+/// Never reuse an IV with the same passphrase; `encrypt` picks a random
+/// one.
+pub fn encrypt_with_iv(
+  passphrase: &[u8],
+  iv: &[u8; IV_LEN],
+  msg: &[u8],
+  rounds: usize
+) -> Result<Vec<u8>> {
+  let mut rc4 = keystream(passphrase, iv, rounds)?;
+
+  let mut out = Vec::with_capacity(IV_LEN + msg.len());
+  out.extend_from_slice(iv);
+  out.extend_from_slice(msg);
+  rc4.apply_keystream(&mut out[IV_LEN..]);
+
+  Ok(out)
+}
+
+/// Decrypts a whole CipherSaber file.
+pub fn decrypt(passphrase: &[u8], file: &[u8], rounds: usize) -> Result<Vec<u8>> {
+  if file.len() < IV_LEN {
+    return Err(Error::Format("CipherSaber file is shorter than its IV"));
+  }
+
+  let (iv, body) = file.split_at(IV_LEN);
+  let mut rc4 = keystream(passphrase, iv, rounds)?;
+
+  let mut out = body.to_vec();
+  rc4.apply_keystream(&mut out);
+  Ok(out)
+}
+
+/// Writes a random IV to `inner` and returns a writer that encrypts
+/// everything written to it after that.
+pub fn writer<W: Write>(passphrase: &[u8], rounds: usize, mut inner: W) -> Result<Rc4Writer<W>> {
+  let iv = random_iv()?;
+  let rc4 = keystream(passphrase, &iv, rounds)?;
+
+  inner.write_all(&iv)?;
+  Ok(Rc4Writer::from_keystream(rc4, inner))
+}
+
+/// Reads the IV from `inner` and returns a reader that decrypts the
+/// rest of the file.
+pub fn reader<R: Read>(passphrase: &[u8], rounds: usize, mut inner: R) -> Result<Rc4Reader<R>> {
+  let mut iv = [0u8; IV_LEN];
+  inner.read_exact(&mut iv)?;
+
+  let rc4 = keystream(passphrase, &iv, rounds)?;
+  Ok(Rc4Reader::from_keystream(rc4, inner))
+}
+
+fn keystream(passphrase: &[u8], iv: &[u8], rounds: usize) -> Result<Rc4> {
+  if rounds == 0 {
+    return Err(Error::InvalidParameter("CipherSaber needs at least one key schedule round"));
+  }
+
+  let mut key = Vec::with_capacity(passphrase.len() + iv.len());
+  key.extend_from_slice(passphrase);
+  key.extend_from_slice(iv);
+
+  let rc4 = Rc4::with_schedule(&key, &RepeatedKsa::new(rounds));
+  wipe::wipe(&mut key);
+  rc4
+}
+
+fn random_iv() -> Result<[u8; IV_LEN]> {
+  let mut iv = [0u8; IV_LEN];
+  key::random(&mut iv)?;
+  Ok(iv)
+}
+
+#[cfg(test)]
+mod test {
+  use crate::ciphersaber::{self, CS1_ROUNDS, IV_LEN};
+  use crate::{Error, KeyError};
+  use std::io::{Read, Write};
+
+  // The test files published on the CipherSaber home page.
+  const CSTEST1: &[u8] = include_bytes!("../testdata/ciphersaber/cstest1.cs1");
+  const CSTEST2: &[u8] = include_bytes!("../testdata/ciphersaber/cstest2.cs2");
+
+  #[test]
+  fn test_published_files() {
+    let plain = ciphersaber::decrypt(b"asdfg", CSTEST1, CS1_ROUNDS).unwrap();
+    assert_eq!(plain, b"This is a test of CipherSaber.");
+
+    let plain = ciphersaber::decrypt(b"asdfg", CSTEST2, 10).unwrap();
+    assert_eq!(plain, b"This is a test of CipherSaber-2.");
+
+    let mut reader = ciphersaber::reader(b"asdfg", 10, CSTEST2).unwrap();
+    let mut plain = Vec::new();
+    reader.read_to_end(&mut plain).unwrap();
+    assert_eq!(plain, b"This is a test of CipherSaber-2.");
+  }
+
+  #[test]
+  fn test_encrypt() {
+    let iv: [u8; IV_LEN] = CSTEST1[..IV_LEN].try_into().unwrap();
+    let msg = b"This is a test of CipherSaber.";
+    let file = ciphersaber::encrypt_with_iv(b"asdfg", &iv, msg, CS1_ROUNDS).unwrap();
+    assert_eq!(file, CSTEST1);
+
+    let file = ciphersaber::encrypt(b"asdfg", msg, 20).unwrap();
+    assert_eq!(file.len(), IV_LEN + msg.len());
+    assert_eq!(ciphersaber::decrypt(b"asdfg", &file, 20).unwrap(), msg);
+    assert_ne!(ciphersaber::decrypt(b"asdfg", &file, 19).unwrap(), msg);
+  }
+
+  #[test]
+  fn test_writer() {
+    let msg = b"This is a test of CipherSaber-2.";
+    let mut writer = ciphersaber::writer(b"asdfg", 10, Vec::new()).unwrap();
+    writer.write_all(msg).unwrap();
+
+    let file = writer.into_inner();
+    assert_eq!(ciphersaber::decrypt(b"asdfg", &file, 10).unwrap(), msg);
+  }
+
+  #[test]
+  fn test_errors() {
+    assert!(matches!(ciphersaber::decrypt(b"asdfg", &CSTEST1[..9], 1), Err(Error::Format(_))));
+    assert!(matches!(ciphersaber::reader(b"asdfg", 1, &CSTEST1[..9]), Err(Error::Io(_))));
+    assert!(matches!(ciphersaber::encrypt(b"asdfg", b"", 0), Err(Error::InvalidParameter(_))));
+    assert!(matches!(
+      ciphersaber::encrypt(&[0; 247], b"", 1),
+      Err(Error::Key(KeyError::KeyTooLong { len: 257 }))
+    ));
+  }
+}
@@ -0,0 +1,535 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An authenticated, versioned container around RC4-drop.
+//!
+//! The file starts with a header:
+//!
+//! ```text
+//! magic "RC4C" | version (1) | drop (u32 BE) | chunk size (u32 BE) | nonce (16)
+//! ```
+//!
+//! Encryption and MAC keys are derived from the master key, the header
+//! and a label with HMAC-SHA256, so every file gets fresh keys. The
+//! plaintext is encrypted with a single RC4 keystream that skips the
+//! first `drop` bytes, and split into chunks of `chunk size` bytes, each
+//! followed by a 32-byte HMAC-SHA256 tag over the header, the chunk
+//! index, a final-chunk flag and the chunk's ciphertext.
+//!
+//! Every chunk except the last is exactly `chunk size` bytes long; the
+//! last one is shorter, possibly empty, and flagged as final, so
+//! truncated, reordered or extended files are rejected. `Reader` checks
+//! each tag in constant time before it releases any of that chunk's
+//! plaintext.
+
+use std::io::{self, Read, Write};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{ct, key, wipe, Error, Rc4, Result};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The magic bytes at the start of every container.
+pub const MAGIC: &[u8; 4] = b"RC4C";
+
+/// The only format version so far.
+pub const VERSION: u8 = 1;
+
+/// The length of the random nonce in the header.
+pub const NONCE_LEN: usize = 16;
+
+/// The length of the header.
+pub const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + 4 + NONCE_LEN;
+
+/// The length of the tag after every chunk.
+pub const TAG_LEN: usize = 32;
+
+/// The shortest master key accepted.
+pub const MIN_MASTER_KEY_LEN: usize = 16;
+
+/// The largest chunk size, which bounds the memory a reader needs.
+pub const MAX_CHUNK_SIZE: u32 = 1 << 24;
+
+/// The most keystream bytes a header may ask to discard.
+pub const MAX_DROP: u32 = 1 << 20;
+
+/// Format parameters chosen by the writer and recorded in the header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Params {
+  /// The number of initial keystream bytes to discard.
+  pub drop: u32,
+  /// The plaintext bytes per chunk, from 1 to `MAX_CHUNK_SIZE`.
+  pub chunk_size: u32
+}
+
+impl Default for Params {
+  /// `RC4-drop[3072]` with 64 KiB chunks.
+  fn default() -> Params {
+    Params { drop: 3072, chunk_size: 64 * 1024 }
+  }
+}
+
+impl Params {
+  fn check(&self) -> Result<()> {
+    if self.chunk_size == 0 || self.chunk_size > MAX_CHUNK_SIZE {
+      return Err(Error::InvalidParameter("chunk size must be from 1 to MAX_CHUNK_SIZE"));
+    }
+
+    if self.drop > MAX_DROP {
+      return Err(Error::InvalidParameter("drop must be at most MAX_DROP"));
+    }
+
+    Ok(())
+  }
+}
+
+/// Encrypts `msg` into a container with the default parameters.
+pub fn seal(master_key: &[u8], msg: &[u8]) -> Result<Vec<u8>> {
+  let mut writer = Writer::new(master_key, Vec::new())?;
+  writer.write_all(msg)?;
+  writer.finish()
+}
+
+/// Verifies and decrypts a whole container.
+pub fn open(master_key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+  let mut reader = Reader::new(master_key, data)?;
+  let mut out = Vec::new();
+  reader.read_to_end(&mut out).map_err(unwrap_io)?;
+  Ok(out)
+}
+
+/// Encrypts everything written to it into a container.
+///
+/// Call `finish` when done; a container whose final chunk was never
+/// written is rejected as truncated. After an I/O error the writer
+/// refuses further writes.
+pub struct Writer<W: Write> {
+  inner: W,
+  keys: Keys,
+  chunk: Vec<u8>,
+  chunk_size: usize,
+  index: u64,
+  failed: bool
+}
+
+impl<W: Write> Writer<W> {
+  /// Writes a header with default parameters to `inner`.
+  pub fn new(master_key: &[u8], inner: W) -> Result<Writer<W>> {
+    Writer::with_params(master_key, Params::default(), inner)
+  }
+
+  /// Writes a header with the given parameters to `inner`.
+  pub fn with_params(master_key: &[u8], params: Params, mut inner: W) -> Result<Writer<W>> {
+    params.check()?;
+
+    let mut nonce = [0u8; NONCE_LEN];
+    key::random(&mut nonce)?;
+
+    let header = encode_header(&params, &nonce);
+    let keys = Keys::derive(master_key, &header, params.drop)?;
+    inner.write_all(&header)?;
+
+    let chunk_size = params.chunk_size as usize;
+    Ok(Writer {
+      inner,
+      keys,
+      chunk: Vec::with_capacity(chunk_size),
+      chunk_size,
+      index: 0,
+      failed: false
+    })
+  }
+
+  /// Writes the final chunk and returns the underlying writer.
+  pub fn finish(mut self) -> Result<W> {
+    self.write_chunk(true)?;
+    self.inner.flush()?;
+    Ok(self.inner)
+  }
+
+  fn write_chunk(&mut self, last: bool) -> Result<()> {
+    if self.failed {
+      return Err(Error::Io(io::Error::other("container writer failed earlier")));
+    }
+
+    self.keys.rc4.try_apply_keystream(&mut self.chunk)?;
+    let tag = self.keys.tag(self.index, last, &self.chunk);
+
+    self.failed = true;
+    self.inner.write_all(&self.chunk)?;
+    self.inner.write_all(&tag)?;
+    self.failed = false;
+
+    self.chunk.clear();
+    self.index += 1;
+    Ok(())
+  }
+}
+
+impl<W: Write> Write for Writer<W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let num = buf.len().min(self.chunk_size - self.chunk.len());
+    self.chunk.extend_from_slice(&buf[..num]);
+
+    // Only the last chunk may be short, so a full chunk is never final.
+    if self.chunk.len() == self.chunk_size {
+      self.write_chunk(false)?;
+    }
+
+    Ok(num)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+/// Verifies and decrypts a container, one chunk at a time.
+///
+/// Reads fail with `io::ErrorKind::InvalidData` wrapping
+/// `Error::AuthenticationFailed` when a tag doesn't match, and no
+/// plaintext from that chunk is returned. After any error, including
+/// one from the inner reader, the reader refuses further reads.
+pub struct Reader<R: Read> {
+  inner: R,
+  keys: Keys,
+  chunk: Vec<u8>,
+  chunk_size: usize,
+  index: u64,
+  pos: usize,
+  done: bool,
+  failed: bool
+}
+
+impl<R: Read> Reader<R> {
+  /// Reads and checks the header from `inner`.
+  pub fn new(master_key: &[u8], mut inner: R) -> Result<Reader<R>> {
+    let mut header = [0u8; HEADER_LEN];
+    inner.read_exact(&mut header)?;
+
+    let params = decode_header(&header)?;
+    let keys = Keys::derive(master_key, &header, params.drop)?;
+
+    let chunk_size = params.chunk_size as usize;
+    Ok(Reader {
+      inner,
+      keys,
+      chunk: Vec::with_capacity(chunk_size + TAG_LEN),
+      chunk_size,
+      index: 0,
+      pos: 0,
+      done: false,
+      failed: false
+    })
+  }
+
+  /// Returns the parameters recorded in the header.
+  pub fn params(&self) -> Params {
+    Params { drop: self.keys.drop, chunk_size: self.chunk_size as u32 }
+  }
+
+  fn read_chunk(&mut self) -> Result<()> {
+    if self.failed {
+      return Err(Error::Io(io::Error::other("container reader failed earlier")));
+    }
+
+    // A chunk that failed to read or verify can't be resynchronised,
+    // and none of its bytes may be released.
+    self.failed = true;
+    let result = self.fill_chunk();
+    if result.is_err() {
+      self.chunk.clear();
+    } else {
+      self.failed = false;
+    }
+
+    result
+  }
+
+  fn fill_chunk(&mut self) -> Result<()> {
+    self.chunk.clear();
+    self.pos = 0;
+
+    let want = (self.chunk_size + TAG_LEN) as u64;
+    (&mut self.inner).take(want).read_to_end(&mut self.chunk)?;
+
+    // A short chunk must be the last one.
+    let last = self.chunk.len() < self.chunk_size + TAG_LEN;
+    if self.chunk.len() < TAG_LEN {
+      return Err(Error::Format("container is truncated"));
+    }
+
+    let len = self.chunk.len() - TAG_LEN;
+    let (data, tag) = self.chunk.split_at(len);
+    if !ct::eq(&self.keys.tag(self.index, last, data), tag) {
+      return Err(Error::AuthenticationFailed);
+    }
+
+    self.chunk.truncate(len);
+    self.keys.rc4.try_apply_keystream(&mut self.chunk)?;
+    self.index += 1;
+    self.done = last;
+    Ok(())
+  }
+}
+
+impl<R: Read> Read for Reader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    while self.pos == self.chunk.len() {
+      if self.done {
+        return Ok(0);
+      }
+
+      self.read_chunk()?;
+    }
+
+    let num = buf.len().min(self.chunk.len() - self.pos);
+    buf[..num].copy_from_slice(&self.chunk[self.pos..self.pos + num]);
+    self.pos += num;
+    Ok(num)
+  }
+}
+
+// Per-file keys derived from the master key and the header.
+struct Keys {
+  rc4: Rc4,
+  mac: HmacSha256,
+  drop: u32
+}
+
+impl Keys {
+  fn derive(master_key: &[u8], header: &[u8; HEADER_LEN], drop: u32) -> Result<Keys> {
+    if master_key.len() < MIN_MASTER_KEY_LEN {
+      return Err(Error::InvalidParameter("master key must be at least MIN_MASTER_KEY_LEN bytes"));
+    }
+
+    let mut enc_key = prf(master_key, b"rc4c encryption", header);
+    let rc4 = Rc4::try_new_drop(&enc_key, drop as usize);
+    wipe::wipe(&mut enc_key);
+
+    let mut mac_key = prf(master_key, b"rc4c authentication", header);
+    let mut mac = mac_from_key(&mac_key);
+    wipe::wipe(&mut mac_key);
+    mac.update(header);
+
+    Ok(Keys { rc4: rc4?, mac, drop })
+  }
+
+  fn tag(&self, index: u64, last: bool, data: &[u8]) -> [u8; TAG_LEN] {
+    let mut mac = self.mac.clone();
+    mac.update(&index.to_be_bytes());
+    mac.update(&[u8::from(last)]);
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+  }
+}
+
+fn prf(key: &[u8], label: &[u8], header: &[u8]) -> [u8; 32] {
+  let mut mac = mac_from_key(key);
+  mac.update(label);
+  mac.update(&[0]);
+  mac.update(header);
+  mac.finalize().into_bytes().into()
+}
+
+fn mac_from_key(key: &[u8]) -> HmacSha256 {
+  // HMAC takes keys of any length.
+  <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts any key length")
+}
+
+fn encode_header(params: &Params, nonce: &[u8; NONCE_LEN]) -> [u8; HEADER_LEN] {
+  let mut header = [0u8; HEADER_LEN];
+  header[..4].copy_from_slice(MAGIC);
+  header[4] = VERSION;
+  header[5..9].copy_from_slice(&params.drop.to_be_bytes());
+  header[9..13].copy_from_slice(&params.chunk_size.to_be_bytes());
+  header[13..].copy_from_slice(nonce);
+  header
+}
+
+fn decode_header(header: &[u8; HEADER_LEN]) -> Result<Params> {
+  if &header[..4] != MAGIC {
+    return Err(Error::Format("not an RC4C container"));
+  }
+
+  if header[4] != VERSION {
+    return Err(Error::Format("unsupported container version"));
+  }
+
+  let params = Params {
+    drop: u32::from_be_bytes(header[5..9].try_into().unwrap()),
+    chunk_size: u32::from_be_bytes(header[9..13].try_into().unwrap())
+  };
+
+  params.check().map_err(|_| Error::Format("container parameters out of range"))?;
+  Ok(params)
+}
+
+// Turns an `io::Error` produced from an `Error` back into the original.
+fn unwrap_io(e: io::Error) -> Error {
+  if e.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+    let inner = e.into_inner().unwrap();
+    return *inner.downcast::<Error>().unwrap();
+  }
+
+  Error::Io(e)
+}
+
+#[cfg(test)]
+mod test {
+  use crate::container::{self, Params, Reader, Writer, HEADER_LEN, TAG_LEN};
+  use crate::Error;
+  use std::io::{self, Read, Write};
+
+  const KEY: &[u8] = b"0123456789abcdef";
+  const SMALL: Params = Params { drop: 768, chunk_size: 16 };
+
+  fn seal_with(params: Params, msg: &[u8]) -> Vec<u8> {
+    let mut writer = Writer::with_params(KEY, params, Vec::new()).unwrap();
+    writer.write_all(msg).unwrap();
+    writer.finish().unwrap()
+  }
+
+  #[test]
+  fn test_roundtrip() {
+    let msg: Vec<u8> = (0..100).collect();
+
+    for len in [0, 1, 15, 16, 17, 32, 100] {
+      let data = seal_with(SMALL, &msg[..len]);
+      let chunks = len / 16 + 1;
+      assert_eq!(data.len(), HEADER_LEN + len + chunks * TAG_LEN);
+      // The nonce is random, so short chunks can encrypt to themselves.
+      if len >= 16 {
+        assert_ne!(&data[HEADER_LEN..HEADER_LEN + len], &msg[..len]);
+      }
+
+      let mut reader = Reader::new(KEY, &data[..]).unwrap();
+      assert_eq!(reader.params(), SMALL);
+
+      let mut out = Vec::new();
+      reader.read_to_end(&mut out).unwrap();
+      assert_eq!(out, &msg[..len]);
+    }
+
+    let data = container::seal(KEY, &msg).unwrap();
+    assert_eq!(container::open(KEY, &data).unwrap(), msg);
+  }
+
+  #[test]
+  fn test_fresh_keys() {
+    let a = container::seal(KEY, b"same message").unwrap();
+    let b = container::seal(KEY, b"same message").unwrap();
+    assert_ne!(a[HEADER_LEN..], b[HEADER_LEN..]);
+  }
+
+  #[test]
+  fn test_tampering() {
+    let msg = [0x42u8; 40];
+    let data = seal_with(SMALL, &msg);
+
+    // Flipping any bit anywhere is detected.
+    for i in 0..data.len() {
+      let mut bad = data.clone();
+      bad[i] ^= 1;
+      assert!(container::open(KEY, &bad).is_err(), "byte {}", i);
+    }
+
+    let wrong_key = container::open(b"fedcba9876543210", &data);
+    assert!(matches!(wrong_key, Err(Error::AuthenticationFailed)));
+
+    // Dropping the final chunk, or everything after the header.
+    let chunk = 16 + TAG_LEN;
+    let cut = container::open(KEY, &data[..HEADER_LEN + 2 * chunk]);
+    assert!(matches!(cut, Err(Error::Format(_))));
+    assert!(matches!(container::open(KEY, &data[..HEADER_LEN]), Err(Error::Format(_))));
+
+    // Swapping the first two chunks.
+    let mut swapped = data[..HEADER_LEN].to_vec();
+    swapped.extend_from_slice(&data[HEADER_LEN + chunk..HEADER_LEN + 2 * chunk]);
+    swapped.extend_from_slice(&data[HEADER_LEN..HEADER_LEN + chunk]);
+    swapped.extend_from_slice(&data[HEADER_LEN + 2 * chunk..]);
+    assert!(matches!(container::open(KEY, &swapped), Err(Error::AuthenticationFailed)));
+
+    // Appending a byte turns the final chunk into a bad full one.
+    let mut longer = data.clone();
+    longer.push(0);
+    assert!(container::open(KEY, &longer).is_err());
+  }
+
+  #[test]
+  fn test_verified_chunks_only() {
+    let msg: Vec<u8> = (0..40).collect();
+    let mut data = seal_with(SMALL, &msg);
+    data[HEADER_LEN + 16 + TAG_LEN] ^= 1;
+
+    let mut reader = Reader::new(KEY, &data[..]).unwrap();
+    let mut buf = [0u8; 40];
+    assert_eq!(reader.read(&mut buf).unwrap(), 16);
+    assert_eq!(buf[..16], msg[..16]);
+    assert!(reader.read(&mut buf).is_err());
+  }
+
+  // Fails once with `TimedOut` after `fail_at` bytes.
+  struct Flaky<'a> {
+    data: &'a [u8],
+    fail_at: usize
+  }
+
+  impl Read for Flaky<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+      if self.fail_at == 0 {
+        self.fail_at = usize::MAX;
+        return Err(io::ErrorKind::TimedOut.into());
+      }
+
+      let num = buf.len().min(self.data.len()).min(self.fail_at);
+      buf[..num].copy_from_slice(&self.data[..num]);
+      self.data = &self.data[num..];
+      self.fail_at -= num;
+      Ok(num)
+    }
+  }
+
+  #[test]
+  fn test_inner_error() {
+    let msg = [0x42u8; 40];
+    let data = seal_with(SMALL, &msg);
+
+    // Fail halfway through the second chunk.
+    let fail_at = HEADER_LEN + 16 + TAG_LEN + 20;
+    let mut reader = Reader::new(KEY, Flaky { data: &data, fail_at }).unwrap();
+    let mut buf = [0u8; 40];
+    assert_eq!(reader.read(&mut buf).unwrap(), 16);
+    assert_eq!(reader.read(&mut buf).unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+    // Nothing from the half-read chunk comes out afterwards.
+    for _ in 0..3 {
+      assert!(reader.read(&mut buf).is_err());
+    }
+  }
+
+  #[test]
+  fn test_bad_header() {
+    let data = seal_with(SMALL, b"msg");
+
+    let mut bad = data.clone();
+    bad[4] = 2;
+    assert!(matches!(Reader::new(KEY, &bad[..]), Err(Error::Format(_))));
+
+    let mut bad = data.clone();
+    bad[9..13].copy_from_slice(&0u32.to_be_bytes());
+    assert!(matches!(Reader::new(KEY, &bad[..]), Err(Error::Format(_))));
+
+    assert!(matches!(Reader::new(KEY, &data[..10]), Err(Error::Io(_))));
+    assert!(matches!(Reader::new(b"short", &data[..]), Err(Error::InvalidParameter(_))));
+
+    let params = Params { drop: 0, chunk_size: 0 };
+    assert!(matches!(
+      Writer::with_params(KEY, params, Vec::new()),
+      Err(Error::InvalidParameter(_))
+    ));
+  }
+}
@@ -0,0 +1,29 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::hint::black_box;
+
+/// Compares two byte strings in time that depends only on their
+/// lengths, not on their contents.
+pub(crate) fn eq(a: &[u8], b: &[u8]) -> bool {
+  if a.len() != b.len() {
+    return false;
+  }
+
+  let diff = a.iter().zip(b).fold(0u8, |d, (x, y)| d | (x ^ y));
+  black_box(diff) == 0
+}
+
+#[cfg(test)]
+mod test {
+  use crate::ct;
+
+  #[test]
+  fn test_eq() {
+    assert!(ct::eq(b"", b""));
+    assert!(ct::eq(b"tag", b"tag"));
+    assert!(!ct::eq(b"tag", b"tah"));
+    assert!(!ct::eq(b"tag", b"tags"));
+  }
+}
@@ -0,0 +1,86 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use crate::KeyError;
+
+/// A specialized `Result` type for this crate.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Errors returned by this crate.
+#[derive(Debug)]
+pub enum Error {
+  /// The key was rejected.
+  Key(KeyError),
+  /// The keystream position would overflow a `u64`.
+  KeystreamExhausted,
+  /// A MAC or authentication tag did not match.
+  AuthenticationFailed,
+  /// A message's sequence counter wasn't higher than the last one
+  /// accepted, so it may have been replayed.
+  Replay,
+  /// A parameter is outside the range the operation supports.
+  InvalidParameter(&'static str),
+  /// Encrypted input is truncated or malformed.
+  Format(&'static str),
+  /// The wrapped reader or writer failed.
+  Io(io::Error)
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Error::Key(ref e) => e.fmt(f),
+      Error::KeystreamExhausted => write!(f, "RC4 keystream position overflowed"),
+      Error::AuthenticationFailed => write!(f, "authentication failed"),
+      Error::Replay => write!(f, "replayed or reordered message"),
+      Error::InvalidParameter(what) => write!(f, "invalid parameter: {}", what),
+      Error::Format(what) => write!(f, "malformed input: {}", what),
+      Error::Io(ref e) => e.fmt(f)
+    }
+  }
+}
+
+impl error::Error for Error {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match *self {
+      Error::Key(ref e) => Some(e),
+      Error::KeystreamExhausted
+      | Error::AuthenticationFailed
+      | Error::Replay
+      | Error::InvalidParameter(_)
+      | Error::Format(_) => None,
+      Error::Io(ref e) => Some(e)
+    }
+  }
+}
+
+impl From<KeyError> for Error {
+  fn from(e: KeyError) -> Error {
+    Error::Key(e)
+  }
+}
+
+impl From<io::Error> for Error {
+  fn from(e: io::Error) -> Error {
+    Error::Io(e)
+  }
+}
+
+impl From<Error> for io::Error {
+  fn from(e: Error) -> io::Error {
+    match e {
+      Error::Io(e) => e,
+      Error::Key(_) | Error::InvalidParameter(_) => io::Error::new(io::ErrorKind::InvalidInput, e),
+      Error::AuthenticationFailed | Error::Replay | Error::Format(_) => {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+      }
+      Error::KeystreamExhausted => io::Error::other(e)
+    }
+  }
+}
@@ -0,0 +1,176 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Deriving RC4 keys from passphrases.
+//!
+//! Both PBKDF2-HMAC-SHA256 and Argon2id produce a validated `Rc4Key` of
+//! 1 to 256 bytes. Argon2id is the better choice for new formats; it
+//! can't produce keys shorter than 4 bytes.
+
+use argon2::{Algorithm, Argon2, Version};
+use sha2::Sha256;
+
+use crate::{key, Error, Rc4Key, Result};
+
+/// The salt length `random_salt` generates.
+pub const SALT_LEN: usize = 16;
+
+/// Argon2id cost parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Argon2Params {
+  /// Memory in KiB.
+  pub memory_kib: u32,
+  /// Passes over the memory.
+  pub iterations: u32,
+  /// Degree of parallelism.
+  pub lanes: u32
+}
+
+impl Default for Argon2Params {
+  /// 19 MiB, two passes and one lane, as OWASP recommends.
+  fn default() -> Argon2Params {
+    Argon2Params { memory_kib: 19 * 1024, iterations: 2, lanes: 1 }
+  }
+}
+
+/// A password-based key derivation function and its cost.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kdf {
+  Pbkdf2Sha256 { iterations: u32 },
+  Argon2id(Argon2Params)
+}
+
+impl Kdf {
+  /// Derives a `len`-byte key from `passphrase` and `salt`.
+  pub fn derive(&self, passphrase: &[u8], salt: &[u8], len: usize) -> Result<Rc4Key> {
+    match *self {
+      Kdf::Pbkdf2Sha256 { iterations } => pbkdf2_sha256(passphrase, salt, iterations, len),
+      Kdf::Argon2id(params) => argon2id(passphrase, salt, &params, len)
+    }
+  }
+}
+
+/// Derives a `len`-byte key with PBKDF2-HMAC-SHA256.
+pub fn pbkdf2_sha256(
+  passphrase: &[u8],
+  salt: &[u8],
+  iterations: u32,
+  len: usize
+) -> Result<Rc4Key> {
+  check_salt(salt)?;
+  key::check_len(len)?;
+
+  if iterations == 0 {
+    return Err(Error::InvalidParameter("PBKDF2 needs at least one iteration"));
+  }
+
+  let mut out = vec![0u8; len];
+  pbkdf2::pbkdf2_hmac::<Sha256>(passphrase, salt, iterations, &mut out);
+  Rc4Key::from_vec(out)
+}
+
+/// Derives a `len`-byte key with Argon2id version 1.3.
+pub fn argon2id(
+  passphrase: &[u8],
+  salt: &[u8],
+  params: &Argon2Params,
+  len: usize
+) -> Result<Rc4Key> {
+  check_salt(salt)?;
+  key::check_len(len)?;
+
+  let params = argon2::Params::new(params.memory_kib, params.iterations, params.lanes, Some(len))
+    .map_err(|_| Error::InvalidParameter("invalid Argon2 parameters"))?;
+
+  let mut out = vec![0u8; len];
+  let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+  if argon2.hash_password_into(passphrase, salt, &mut out).is_err() {
+    // Argon2 rejects salts shorter than 8 bytes.
+    return Err(Error::InvalidParameter("invalid Argon2 salt"));
+  }
+
+  Rc4Key::from_vec(out)
+}
+
+/// Returns a fresh random salt.
+pub fn random_salt() -> Result<[u8; SALT_LEN]> {
+  let mut salt = [0u8; SALT_LEN];
+  key::random(&mut salt)?;
+  Ok(salt)
+}
+
+fn check_salt(salt: &[u8]) -> Result<()> {
+  if salt.is_empty() {
+    return Err(Error::InvalidParameter("salt must not be empty"));
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use crate::kdf::{self, Argon2Params, Kdf};
+  use crate::test_util::to_hex;
+  use crate::{Error, KeyError};
+
+  const CHEAP: Argon2Params = Argon2Params { memory_kib: 64, iterations: 2, lanes: 1 };
+
+  #[test]
+  fn test_pbkdf2() {
+    // RFC 7914, section 11.
+    let key = kdf::pbkdf2_sha256(b"passwd", b"salt", 1, 64).unwrap();
+    assert_eq!(
+      to_hex(key.as_bytes()),
+      "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc\
+       49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+    );
+
+    let key = kdf::pbkdf2_sha256(b"pw", b"saltsalt", 10, 256).unwrap();
+    assert_eq!(to_hex(&key.as_bytes()[240..]), "5dfd6025b112aa5986bd433a0f8b0233");
+  }
+
+  // The Argon2id vectors come from Python's `cryptography` package.
+  #[test]
+  fn test_argon2id() {
+    let pass = b"correct horse battery staple";
+
+    let key = kdf::argon2id(pass, b"saltsaltsalt", &CHEAP, 32).unwrap();
+    assert_eq!(
+      to_hex(key.as_bytes()),
+      "7e3e1e04b0b562e4b7b15334951fc88e574fafaace52c053fe64b0f56dac2424"
+    );
+
+    let key = Kdf::Argon2id(CHEAP).derive(pass, b"saltsaltsalt", 5).unwrap();
+    assert_eq!(to_hex(key.as_bytes()), "7de2e9153f");
+
+    let params = Argon2Params { memory_kib: 128, iterations: 3, lanes: 2 };
+    let key = kdf::argon2id(b"pw", b"saltsaltsalt", &params, 256).unwrap();
+    assert_eq!(to_hex(&key.as_bytes()[..16]), "3443381c1ae2a5548a54f44545aee087");
+  }
+
+  #[test]
+  fn test_errors() {
+    let pbkdf2 = Kdf::Pbkdf2Sha256 { iterations: 1 };
+    let argon2 = Kdf::Argon2id(CHEAP);
+
+    for kdf in [pbkdf2, argon2] {
+      assert!(matches!(kdf.derive(b"pw", b"saltsalt", 0), Err(Error::Key(KeyError::EmptyKey))));
+      assert!(matches!(
+        kdf.derive(b"pw", b"saltsalt", 257),
+        Err(Error::Key(KeyError::KeyTooLong { len: 257 }))
+      ));
+      assert!(matches!(kdf.derive(b"pw", b"", 16), Err(Error::InvalidParameter(_))));
+    }
+
+    let zero = Kdf::Pbkdf2Sha256 { iterations: 0 };
+    assert!(matches!(zero.derive(b"pw", b"saltsalt", 16), Err(Error::InvalidParameter(_))));
+    assert!(matches!(argon2.derive(b"pw", b"salt", 16), Err(Error::InvalidParameter(_))));
+    assert!(matches!(argon2.derive(b"pw", b"saltsalt", 3), Err(Error::InvalidParameter(_))));
+  }
+
+  #[test]
+  fn test_random_salt() {
+    assert_ne!(kdf::random_salt().unwrap(), kdf::random_salt().unwrap());
+  }
+}
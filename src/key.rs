@@ -0,0 +1,143 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::error;
+use std::fmt;
+
+use crate::{ct, wipe, Result};
+
+/// The largest key the RC4 key schedule can make use of.
+pub const MAX_KEY_LEN: usize = 256;
+
+/// Reasons a key can be rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyError {
+  /// The key has no bytes.
+  EmptyKey,
+  /// The key is longer than `MAX_KEY_LEN` bytes.
+  KeyTooLong { len: usize },
+  /// The IV has no bytes.
+  EmptyIv,
+  /// The IV is longer than the cipher allows.
+  IvTooLong { len: usize }
+}
+
+impl fmt::Display for KeyError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      KeyError::EmptyKey => write!(f, "RC4 key must not be empty"),
+      KeyError::KeyTooLong { len } => {
+        write!(f, "RC4 key is {} bytes, at most {} allowed", len, MAX_KEY_LEN)
+      }
+      KeyError::EmptyIv => write!(f, "IV must not be empty"),
+      KeyError::IvTooLong { len } => write!(f, "IV of {} bytes is too long", len)
+    }
+  }
+}
+
+impl error::Error for KeyError {}
+
+/// An RC4 key of 1 to 256 bytes.
+///
+/// The key bytes are zeroized on drop and redacted from `Debug`, and
+/// keys compare in constant time.
+#[derive(Clone)]
+pub struct Rc4Key(Vec<u8>);
+
+impl Rc4Key {
+  /// Checks the length of `key` and copies it.
+  pub fn new(key: &[u8]) -> Result<Rc4Key> {
+    check(key)?;
+    Ok(Rc4Key(key.to_vec()))
+  }
+
+  /// Takes ownership of `key`, which is zeroized if it is rejected.
+  #[cfg(any(feature = "kdf", feature = "openssl"))]
+  pub(crate) fn from_vec(key: Vec<u8>) -> Result<Rc4Key> {
+    let key = Rc4Key(key);
+    check(&key.0)?;
+    Ok(key)
+  }
+
+  /// Returns the key bytes.
+  pub fn as_bytes(&self) -> &[u8] {
+    &self.0
+  }
+}
+
+impl Drop for Rc4Key {
+  fn drop(&mut self) {
+    wipe::wipe(&mut self.0);
+  }
+}
+
+impl PartialEq for Rc4Key {
+  fn eq(&self, other: &Rc4Key) -> bool {
+    ct::eq(&self.0, &other.0)
+  }
+}
+
+impl Eq for Rc4Key {}
+
+impl fmt::Debug for Rc4Key {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Rc4Key").finish_non_exhaustive()
+  }
+}
+
+impl AsRef<[u8]> for Rc4Key {
+  fn as_ref(&self) -> &[u8] {
+    &self.0
+  }
+}
+
+impl TryFrom<&[u8]> for Rc4Key {
+  type Error = crate::Error;
+
+  fn try_from(key: &[u8]) -> Result<Rc4Key> {
+    Rc4Key::new(key)
+  }
+}
+
+pub(crate) fn check(key: &[u8]) -> Result<()> {
+  check_len(key.len())
+}
+
+pub(crate) fn check_len(len: usize) -> Result<()> {
+  match len {
+    0 => Err(KeyError::EmptyKey.into()),
+    len if len > MAX_KEY_LEN => Err(KeyError::KeyTooLong { len }.into()),
+    _ => Ok(())
+  }
+}
+
+/// Fills `buf` from the operating system's random number generator.
+#[cfg(feature = "getrandom")]
+pub(crate) fn random(buf: &mut [u8]) -> Result<()> {
+  getrandom::getrandom(buf).map_err(|e| crate::Error::Io(e.into()))
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{Error, KeyError, Rc4Key};
+
+  #[test]
+  fn test_key_len() {
+    assert!(matches!(Rc4Key::new(b""), Err(Error::Key(KeyError::EmptyKey))));
+    assert!(matches!(
+      Rc4Key::new(&[0; 257]),
+      Err(Error::Key(KeyError::KeyTooLong { len: 257 }))
+    ));
+    assert_eq!(Rc4Key::new(b"K").unwrap().as_bytes(), b"K");
+    assert_eq!(Rc4Key::new(&[7; 256]).unwrap().as_bytes(), &[7; 256][..]);
+  }
+
+  #[test]
+  fn test_eq() {
+    let key = Rc4Key::new(b"Key").unwrap();
+    assert_eq!(key, Rc4Key::new(b"Key").unwrap());
+    assert_ne!(key, Rc4Key::new(b"Kez").unwrap());
+    assert_ne!(key, Rc4Key::new(b"Keys").unwrap());
+  }
+}
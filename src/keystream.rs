@@ -0,0 +1,303 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::io::{self, Read, Write};
+
+use crate::{Rc4, Result};
+
+/// A source of keystream bytes.
+///
+/// Implementing this is all it takes for a generator to be usable with
+/// `KeystreamReader` and `KeystreamWriter`.
+pub trait KeystreamGenerator {
+  /// XORs the next `buf.len()` keystream bytes into `buf`.
+  fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<()>;
+
+  /// Advances the keystream by `n` bytes without producing output.
+  fn skip(&mut self, n: u64) -> Result<()>;
+
+  /// Returns the number of keystream bytes consumed so far.
+  fn position(&self) -> u64;
+
+  /// Overwrites `buf` with the next `buf.len()` keystream bytes.
+  fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+    buf.fill(0);
+    self.try_apply_keystream(buf)
+  }
+}
+
+impl KeystreamGenerator for Rc4 {
+  fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<()> {
+    Rc4::try_apply_keystream(self, buf)
+  }
+
+  fn skip(&mut self, n: u64) -> Result<()> {
+    Rc4::skip(self, n)
+  }
+
+  fn position(&self) -> u64 {
+    Rc4::position(self)
+  }
+}
+
+/// Encrypts or decrypts everything read from the inner reader with the
+/// keystream from `G`.
+pub struct KeystreamReader<R, G> {
+  raw: G,
+  data: R
+}
+
+/// A `KeystreamReader` using plain RC4.
+pub type Rc4Reader<R> = KeystreamReader<R, Rc4>;
+
+impl<R: Read> KeystreamReader<R, Rc4> {
+  /// Wraps `data`, keying the stream with `key`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `key` is empty or longer than 256 bytes.
+  pub fn new(key: &[u8], data: R) -> Rc4Reader<R> {
+    KeystreamReader::from_keystream(Rc4::new(key), data)
+  }
+
+  /// Wraps `data`, rejecting keys that are empty or longer than 256
+  /// bytes.
+  pub fn try_new(key: &[u8], data: R) -> Result<Rc4Reader<R>> {
+    Ok(KeystreamReader::from_keystream(Rc4::try_new(key)?, data))
+  }
+
+  /// Wraps `data`, discarding the first `n` keystream bytes.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `key` is empty or longer than 256 bytes.
+  pub fn new_drop(key: &[u8], n: usize, data: R) -> Rc4Reader<R> {
+    KeystreamReader::from_keystream(Rc4::new_drop(key, n), data)
+  }
+}
+
+impl<R: Read, G: KeystreamGenerator> KeystreamReader<R, G> {
+  /// Wraps `data`, using `raw` as the keystream.
+  pub fn from_keystream(raw: G, data: R) -> KeystreamReader<R, G> {
+    KeystreamReader { raw, data }
+  }
+
+  /// Returns the keystream generator.
+  pub fn keystream(&self) -> &G {
+    &self.raw
+  }
+
+  /// Unwraps this `KeystreamReader`, returning the underlying reader.
+  pub fn into_inner(self) -> R {
+    self.data
+  }
+}
+
+impl<R: Read, G: KeystreamGenerator> Read for KeystreamReader<R, G> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    // Only the bytes the inner reader produced consume keystream, so
+    // errors, interruptions and EOF leave the stream in sync.
+    let num = self.data.read(buf)?;
+    self.raw.try_apply_keystream(&mut buf[..num])?;
+
+    Ok(num)
+  }
+}
+
+/// Encrypts or decrypts everything written to the inner writer with
+/// the keystream from `G`.
+///
+/// `G` must be `Clone` so that the keystream can be rewound when the
+/// inner writer accepts only part of a buffer.
+pub struct KeystreamWriter<W, G> {
+  raw: G,
+  data: W
+}
+
+/// A `KeystreamWriter` using plain RC4.
+pub type Rc4Writer<W> = KeystreamWriter<W, Rc4>;
+
+impl<W: Write> KeystreamWriter<W, Rc4> {
+  /// Wraps `data`, keying the stream with `key`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `key` is empty or longer than 256 bytes.
+  pub fn new(key: &[u8], data: W) -> Rc4Writer<W> {
+    KeystreamWriter::from_keystream(Rc4::new(key), data)
+  }
+
+  /// Wraps `data`, rejecting keys that are empty or longer than 256
+  /// bytes.
+  pub fn try_new(key: &[u8], data: W) -> Result<Rc4Writer<W>> {
+    Ok(KeystreamWriter::from_keystream(Rc4::try_new(key)?, data))
+  }
+
+  /// Wraps `data`, discarding the first `n` keystream bytes.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `key` is empty or longer than 256 bytes.
+  pub fn new_drop(key: &[u8], n: usize, data: W) -> Rc4Writer<W> {
+    KeystreamWriter::from_keystream(Rc4::new_drop(key, n), data)
+  }
+}
+
+impl<W: Write, G: KeystreamGenerator> KeystreamWriter<W, G> {
+  /// Wraps `data`, using `raw` as the keystream.
+  pub fn from_keystream(raw: G, data: W) -> KeystreamWriter<W, G> {
+    KeystreamWriter { raw, data }
+  }
+
+  /// Returns the keystream generator.
+  pub fn keystream(&self) -> &G {
+    &self.raw
+  }
+
+  /// Unwraps this `KeystreamWriter`, returning the underlying writer.
+  pub fn into_inner(self) -> W {
+    self.data
+  }
+}
+
+impl<W: Write, G: KeystreamGenerator + Clone> Write for KeystreamWriter<W, G> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let mut out = [0u8; 4096];
+    let len = buf.len().min(out.len());
+
+    // Keep a copy of the keystream state in case the inner
+    // writer doesn't accept all of the bytes we hand it.
+    let saved = self.raw.clone();
+
+    out[..len].copy_from_slice(&buf[..len]);
+    self.raw.try_apply_keystream(&mut out[..len])?;
+
+    let num = match self.data.write(&out[..len]) {
+      Ok(num) => num,
+      Err(e) => {
+        self.raw = saved;
+        return Err(e);
+      }
+    };
+
+    if num < len {
+      self.raw = saved;
+      self.raw.skip(num as u64)?;
+    }
+
+    Ok(num)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.data.flush()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{KeystreamGenerator, KeystreamReader, KeystreamWriter, Result};
+  use crate::{Rc4, Rc4A, Rc4Plus, Vmpc};
+  use std::io::{self, Read, Write};
+
+  // A toy generator whose keystream is the byte position itself.
+  #[derive(Clone)]
+  struct Counter(u64);
+
+  impl KeystreamGenerator for Counter {
+    fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<()> {
+      for b in buf.iter_mut() {
+        *b ^= self.0 as u8;
+        self.0 += 1;
+      }
+
+      Ok(())
+    }
+
+    fn skip(&mut self, n: u64) -> Result<()> {
+      self.0 += n;
+      Ok(())
+    }
+
+    fn position(&self) -> u64 {
+      self.0
+    }
+  }
+
+  // Accepts at most five bytes per write, and fails every third one.
+  struct Flaky {
+    data: Vec<u8>,
+    calls: usize
+  }
+
+  impl Write for Flaky {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+      self.calls += 1;
+
+      if self.calls.is_multiple_of(3) {
+        return Err(io::ErrorKind::Interrupted.into());
+      }
+
+      let num = buf.len().min(5);
+      self.data.extend_from_slice(&buf[..num]);
+      Ok(num)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  // Runs the same I/O checks against any generator.
+  fn check_generator<G: KeystreamGenerator + Clone>(make: impl Fn() -> G) {
+    let plain: Vec<u8> = (0..5000).map(|b| (b * 7) as u8).collect();
+
+    let mut expected = plain.clone();
+    let mut raw = make();
+    let start = raw.position();
+    raw.try_apply_keystream(&mut expected).unwrap();
+    let end = raw.position();
+    assert_eq!(end - start, plain.len() as u64);
+
+    // Writing through a flaky writer matches the bulk keystream.
+    let flaky = Flaky { data: Vec::new(), calls: 0 };
+    let mut writer = KeystreamWriter::from_keystream(make(), flaky);
+    writer.write_all(&plain).unwrap();
+    assert_eq!(writer.keystream().position(), end);
+    assert_eq!(writer.into_inner().data, expected);
+
+    // Reading it back recovers the plaintext.
+    let mut reader = KeystreamReader::from_keystream(make(), &expected[..]);
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, plain);
+
+    // `fill` and `skip` agree with `try_apply_keystream`.
+    let mut whole = vec![0u8; 64];
+    make().fill(&mut whole).unwrap();
+
+    let mut raw = make();
+    raw.skip(21).unwrap();
+    let mut tail = vec![0xFFu8; 43];
+    raw.fill(&mut tail).unwrap();
+    assert_eq!(tail, whole[21..]);
+  }
+
+  #[test]
+  fn test_generators() {
+    check_generator(|| Counter(0));
+    check_generator(|| Rc4::new(b"Key"));
+    check_generator(|| Rc4::new_drop(b"Key", 3072));
+    check_generator(|| Rc4A::new(b"Key"));
+    check_generator(|| Vmpc::with_iv(b"Key", b"IV").unwrap());
+    check_generator(|| Rc4Plus::with_iv(b"Key", b"IV").unwrap());
+  }
+
+  #[test]
+  fn test_custom_generator() {
+    let mut reader = KeystreamReader::from_keystream(Counter(0), &[0u8, 0, 0, 0][..]);
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, [0, 1, 2, 3]);
+  }
+}
@@ -0,0 +1,198 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Interchangeable key schedules for the RC4 keystream generator.
+//!
+//! `Rc4::with_schedule` runs any `KeySchedule` and then generates the
+//! usual RC4 keystream from the resulting permutation, which makes it
+//! easy to compare how key schedules affect keystream biases.
+
+use crate::rc4plus::{self, ksa_plus};
+use crate::{identity, ksa, ksa_pass, KeyError, Result};
+
+/// Turns a key into the initial RC4 permutation.
+pub trait KeySchedule {
+  /// Returns the permutation for `key`, which `Rc4::with_schedule`
+  /// ensures is never empty and at most `MAX_KEY_LEN` bytes.
+  ///
+  /// # Panics
+  ///
+  /// The schedules in this module panic if `key` is empty, unless
+  /// they run no rounds; check it first when calling this directly.
+  fn schedule(&self, key: &[u8]) -> [u8; 256];
+}
+
+/// The standard RC4 key schedule: a single pass over the permutation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StandardKsa;
+
+impl KeySchedule for StandardKsa {
+  fn schedule(&self, key: &[u8]) -> [u8; 256] {
+    ksa(key)
+  }
+}
+
+/// The standard key schedule repeated a number of times, with `j`
+/// carried across passes, as used by CipherSaber-2.
+#[derive(Clone, Copy, Debug)]
+pub struct RepeatedKsa {
+  passes: usize
+}
+
+impl RepeatedKsa {
+  /// The number of passes CipherSaber-2 recommends.
+  pub const CIPHERSABER2_PASSES: usize = 20;
+
+  /// Repeats the key schedule `passes` times. Zero passes leave the
+  /// permutation as the identity.
+  pub fn new(passes: usize) -> RepeatedKsa {
+    RepeatedKsa { passes }
+  }
+}
+
+impl KeySchedule for RepeatedKsa {
+  fn schedule(&self, key: &[u8]) -> [u8; 256] {
+    let mut state = identity();
+    let mut j = 0;
+
+    for _ in 0..self.passes {
+      j = ksa_pass(&mut state, key, j);
+    }
+
+    state
+  }
+}
+
+/// The three-layer RC4+ key schedule, which mixes in an IV.
+#[derive(Clone, Debug)]
+pub struct Rc4PlusKsa {
+  iv: Vec<u8>
+}
+
+impl Rc4PlusKsa {
+  /// Uses `iv`, which must be at most 128 bytes.
+  pub fn new(iv: &[u8]) -> Result<Rc4PlusKsa> {
+    if iv.len() > rc4plus::MAX_IV_LEN {
+      return Err(KeyError::IvTooLong { len: iv.len() }.into());
+    }
+
+    Ok(Rc4PlusKsa { iv: iv.to_vec() })
+  }
+}
+
+impl KeySchedule for Rc4PlusKsa {
+  fn schedule(&self, key: &[u8]) -> [u8; 256] {
+    ksa_plus(key, &self.iv)
+  }
+}
+
+/// A research key schedule that visits the permutation in a
+/// pseudorandom order instead of sequentially.
+///
+/// Each of `rounds` steps picks `i` from a SplitMix64 sequence seeded
+/// with `seed`, then updates `j` and swaps exactly like the standard
+/// schedule. The same seed always gives the same permutation, so
+/// experiments are reproducible. It is not meant for real use.
+#[derive(Clone, Copy, Debug)]
+pub struct RandomizedKsa {
+  seed: u64,
+  rounds: usize
+}
+
+impl RandomizedKsa {
+  /// Runs `rounds` steps with indices drawn from `seed`.
+  pub fn new(seed: u64, rounds: usize) -> RandomizedKsa {
+    RandomizedKsa { seed, rounds }
+  }
+}
+
+impl KeySchedule for RandomizedKsa {
+  fn schedule(&self, key: &[u8]) -> [u8; 256] {
+    let mut state = identity();
+    let mut rng = self.seed;
+    let mut j: u8 = 0;
+
+    for n in 0..self.rounds {
+      let i = (splitmix64(&mut rng) >> 56) as usize;
+      j = j.wrapping_add(state[i]).wrapping_add(key[n % key.len()]);
+      state.swap(i, usize::from(j));
+    }
+
+    state
+  }
+}
+
+fn splitmix64(x: &mut u64) -> u64 {
+  *x = x.wrapping_add(0x9E3779B97F4A7C15);
+
+  let mut z = *x;
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+  z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+  use crate::ksa::{KeySchedule, RandomizedKsa, Rc4PlusKsa, RepeatedKsa, StandardKsa};
+  use crate::test_util::to_hex;
+  use crate::{Rc4, Rc4Plus};
+
+  fn is_permutation(state: &[u8; 256]) -> bool {
+    let mut seen = [false; 256];
+    state.iter().for_each(|&x| seen[usize::from(x)] = true);
+    seen.iter().all(|&s| s)
+  }
+
+  #[test]
+  fn test_standard() {
+    let mut rc4 = Rc4::with_schedule(b"Key", &StandardKsa).unwrap();
+    let mut buf = [0u8; 10];
+    rc4.apply_keystream(&mut buf);
+    assert_eq!(to_hex(&buf), "eb9f7781b734ca72a719");
+  }
+
+  #[test]
+  fn test_repeated() {
+    assert_eq!(RepeatedKsa::new(1).schedule(b"Key"), StandardKsa.schedule(b"Key"));
+    assert_eq!(RepeatedKsa::new(0).schedule(b"Key"), crate::identity());
+
+    let state = RepeatedKsa::new(RepeatedKsa::CIPHERSABER2_PASSES).schedule(b"Key");
+    assert!(is_permutation(&state));
+    assert_ne!(state, StandardKsa.schedule(b"Key"));
+  }
+
+  #[test]
+  fn test_rc4plus() {
+    let ksa = Rc4PlusKsa::new(b"IV").unwrap();
+    assert_eq!(ksa.schedule(b"Key"), crate::rc4plus::ksa_plus(b"Key", b"IV"));
+    assert!(Rc4PlusKsa::new(&[0; 129]).is_err());
+
+    // Only the key schedule is swapped, not the RC4+ output function.
+    let mut a = [0u8; 16];
+    let mut b = [0u8; 16];
+    Rc4::with_schedule(b"Key", &ksa).unwrap().apply_keystream(&mut a);
+    Rc4Plus::with_iv(b"Key", b"IV").unwrap().apply_keystream(&mut b);
+    assert_ne!(a, b);
+  }
+
+  #[test]
+  fn test_randomized() {
+    let ksa = RandomizedKsa::new(42, 768);
+    let state = ksa.schedule(b"Key");
+    assert!(is_permutation(&state));
+    assert_eq!(state, RandomizedKsa::new(42, 768).schedule(b"Key"));
+    assert_ne!(state, RandomizedKsa::new(43, 768).schedule(b"Key"));
+  }
+
+  #[test]
+  fn test_bad_key() {
+    assert!(Rc4::with_schedule(b"", &StandardKsa).is_err());
+  }
+
+  #[test]
+  #[should_panic]
+  fn test_empty_key_panics() {
+    StandardKsa.schedule(b"");
+  }
+}
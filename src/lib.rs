@@ -0,0 +1,520 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! RC4 keystream generator and `std::io` adaptors.
+
+use std::fmt;
+use std::io::{self, Read};
+
+#[cfg(feature = "ciphersaber")]
+pub mod ciphersaber;
+#[cfg(feature = "container")]
+pub mod container;
+mod ct;
+mod error;
+#[cfg(feature = "kdf")]
+pub mod kdf;
+mod key;
+mod keystream;
+pub mod ksa;
+#[cfg(feature = "openssl")]
+pub mod openssl;
+#[cfg(feature = "wep")]
+pub mod pcap;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+mod rc4a;
+mod rc4plus;
+#[cfg(test)]
+mod rfc6229;
+pub mod spritz;
+pub mod ssh;
+#[cfg(feature = "cipher")]
+mod stream_cipher;
+#[cfg(test)]
+mod test_util;
+#[cfg(feature = "tkip")]
+pub mod tkip;
+#[cfg(feature = "tls")]
+pub mod tls_record;
+mod vmpc;
+#[cfg(feature = "wep")]
+pub mod wep;
+mod wipe;
+
+pub use error::{Error, Result};
+pub use key::{KeyError, Rc4Key, MAX_KEY_LEN};
+pub use keystream::{
+  KeystreamGenerator, KeystreamReader, KeystreamWriter, Rc4Reader, Rc4Writer
+};
+pub use ksa::KeySchedule;
+pub use rc4a::Rc4A;
+pub use rc4plus::Rc4Plus;
+pub use spritz::Spritz;
+pub use vmpc::Vmpc;
+
+#[cfg(feature = "cipher")]
+pub use cipher;
+
+/// The raw RC4 keystream.
+///
+/// Reading from an `Rc4` fills the buffer with keystream bytes.
+///
+/// The state is zeroized when an `Rc4` is dropped, and its `Debug`
+/// output never includes it.
+#[derive(Clone)]
+pub struct Rc4 {
+  i: u8,
+  j: u8,
+  state: [u8; 256],
+  pos: u64
+}
+
+impl Rc4 {
+  /// Runs the key schedule over `key`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `key` is empty or longer than 256 bytes. Use
+  /// `try_new` to handle bad keys gracefully.
+  pub fn new(key: &[u8]) -> Rc4 {
+    match Rc4::try_new(key) {
+      Ok(rc4) => rc4,
+      Err(e) => panic!("{}", e)
+    }
+  }
+
+  /// Runs the key schedule over `key`, rejecting keys that are empty
+  /// or longer than 256 bytes.
+  pub fn try_new(key: &[u8]) -> Result<Rc4> {
+    key::check(key)?;
+    Ok(Rc4::schedule(key))
+  }
+
+  /// Runs the key schedule over a validated key.
+  pub fn from_key(key: &Rc4Key) -> Rc4 {
+    Rc4::schedule(key.as_bytes())
+  }
+
+  /// Runs `schedule` over `key` instead of the standard RC4 key
+  /// schedule, rejecting keys that are empty or longer than 256 bytes.
+  pub fn with_schedule<K: KeySchedule + ?Sized>(key: &[u8], schedule: &K) -> Result<Rc4> {
+    key::check(key)?;
+
+    let state = schedule.schedule(key);
+    Ok(Rc4 { i: 0, j: 0, state, pos: 0 })
+  }
+
+  /// Runs the key schedule over `key` and discards the first `n`
+  /// keystream bytes (`RC4-drop[n]`).
+  ///
+  /// # Panics
+  ///
+  /// Panics if `key` is empty or longer than 256 bytes.
+  pub fn new_drop(key: &[u8], n: usize) -> Rc4 {
+    let mut rc4 = Rc4::new(key);
+    rc4.advance(n as u64);
+    rc4
+  }
+
+  /// Like `new_drop`, but rejects bad keys instead of panicking.
+  pub fn try_new_drop(key: &[u8], n: usize) -> Result<Rc4> {
+    let mut rc4 = Rc4::try_new(key)?;
+    rc4.advance(n as u64);
+    Ok(rc4)
+  }
+
+  fn schedule(key: &[u8]) -> Rc4 {
+    let state = ksa(key);
+    Rc4 { i: 0, j: 0, state, pos: 0 }
+  }
+
+  /// XORs the keystream into `buf` in place.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the keystream position would overflow a `u64`.
+  pub fn apply_keystream(&mut self, buf: &mut [u8]) {
+    if let Err(e) = self.try_apply_keystream(buf) {
+      panic!("{}", e);
+    }
+  }
+
+  /// XORs the keystream into `buf` in place, failing if the keystream
+  /// position would overflow a `u64`.
+  pub fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<()> {
+    let pos = self.checked_pos(buf.len() as u64)?;
+    let mut i = self.i;
+    let mut j = self.j;
+    let state = &mut self.state;
+
+    // Indexing a [u8; 256] with a u8 can't go out of bounds, so the
+    // compiler drops all bounds checks from this loop.
+    for b in buf.iter_mut() {
+      i = i.wrapping_add(1);
+      let si = state[usize::from(i)];
+      j = j.wrapping_add(si);
+      let sj = state[usize::from(j)];
+
+      state[usize::from(i)] = sj;
+      state[usize::from(j)] = si;
+
+      *b ^= state[usize::from(si.wrapping_add(sj))];
+    }
+
+    self.i = i;
+    self.j = j;
+    self.pos = pos;
+    Ok(())
+  }
+
+  /// Advances the keystream by `n` bytes without producing output.
+  pub fn skip(&mut self, n: u64) -> Result<()> {
+    self.checked_pos(n)?;
+    self.advance(n);
+    Ok(())
+  }
+
+  /// Returns the number of keystream bytes consumed so far, including
+  /// any that were skipped or dropped.
+  pub fn position(&self) -> u64 {
+    self.pos
+  }
+
+  fn checked_pos(&self, n: u64) -> Result<u64> {
+    self.pos.checked_add(n).ok_or(Error::KeystreamExhausted)
+  }
+
+  fn advance(&mut self, n: u64) {
+    let mut i = self.i;
+    let mut j = self.j;
+
+    for _ in 0..n {
+      i = i.wrapping_add(1);
+      j = j.wrapping_add(self.state[usize::from(i)]);
+      self.state.swap(usize::from(i), usize::from(j));
+    }
+
+    self.i = i;
+    self.j = j;
+    self.pos += n;
+  }
+}
+
+/// The RC4 key-scheduling algorithm: permutes the identity with `key`,
+/// which must not be empty.
+pub(crate) fn ksa(key: &[u8]) -> [u8; 256] {
+  let mut state = identity();
+  ksa_pass(&mut state, key, 0);
+  state
+}
+
+pub(crate) fn identity() -> [u8; 256] {
+  let mut state = [0u8; 256];
+
+  for (i, x) in state.iter_mut().enumerate() {
+    *x = i as u8;
+  }
+
+  state
+}
+
+/// One pass of the RC4 key schedule over `state`, starting from `j`.
+/// Returns the final `j`, which some variants carry into later passes.
+pub(crate) fn ksa_pass(state: &mut [u8; 256], key: &[u8], mut j: u8) -> u8 {
+  let klen = key.len();
+
+  for i in 0..256 {
+    j = j.wrapping_add(state[i]).wrapping_add(key[i % klen]);
+    state.swap(i, j as usize);
+  }
+
+  j
+}
+
+impl Drop for Rc4 {
+  fn drop(&mut self) {
+    wipe::wipe(&mut self.state);
+    wipe::wipe_value(&mut self.i);
+    wipe::wipe_value(&mut self.j);
+    wipe::wipe_value(&mut self.pos);
+  }
+}
+
+impl fmt::Debug for Rc4 {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Rc4").finish_non_exhaustive()
+  }
+}
+
+impl Read for Rc4 {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    buf.fill(0);
+    self.try_apply_keystream(buf)?;
+    Ok(buf.len())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::test_util::from_hex;
+  use crate::{Error, KeyError, Rc4, Rc4Key, Rc4Reader, Rc4Writer};
+  use std::io::{self, Read, Write};
+  use std::str::from_utf8;
+
+  #[test]
+  fn test_raw() {
+    test_rc4_raw("Key", "EB9F7781B734CA72A719");
+    test_rc4_raw("Wiki", "6044DB6D41B7");
+    test_rc4_raw("Secret", "04D46B053CA87B59");
+  }
+
+  #[test]
+  fn test_bad_keys() {
+    assert!(matches!(Rc4::try_new(b""), Err(Error::Key(KeyError::EmptyKey))));
+    assert!(matches!(
+      Rc4::try_new(&[0; 257]),
+      Err(Error::Key(KeyError::KeyTooLong { len: 257 }))
+    ));
+    assert!(Rc4Reader::try_new(b"", &b""[..]).is_err());
+    assert!(Rc4Writer::try_new(b"", Vec::new()).is_err());
+
+    let key = Rc4Key::new(b"Key").unwrap();
+    cmp_hex(Rc4::from_key(&key), "EB9F7781B734CA72A719");
+  }
+
+  #[test]
+  #[should_panic]
+  fn test_empty_key_panics() {
+    Rc4::new(b"");
+  }
+
+  #[test]
+  fn test_drop() {
+    // RFC 6229, 40-bit and 128-bit keys at offsets 768, 1536 and 3072.
+    let key40 = from_hex("0102030405");
+    cmp_hex(Rc4::new_drop(&key40, 768), "EB62638D4F0BA1FE9FCA20E05BF8FF2B");
+    cmp_hex(Rc4::new_drop(&key40, 1536), "D8729DB41882259BEE4F825325F5A130");
+    cmp_hex(Rc4::new_drop(&key40, 3072), "EC0E11C479DC329DC8DA7968FE965681");
+
+    let key128 = from_hex("0102030405060708090A0B0C0D0E0F10");
+    cmp_hex(Rc4::new_drop(&key128, 768), "ECCBE13DE1FCC91C11A0B26C0BC8FA4D");
+    cmp_hex(Rc4::new_drop(&key128, 1536), "FFA0B514647EC04F6306B892AE661181");
+    cmp_hex(Rc4::new_drop(&key128, 3072), "C05D88ABD50357F935A63C59EE537623");
+
+    let zeros = [0u8; 16];
+    let stream = Rc4Reader::new_drop(&key128, 1536, &zeros[..]);
+    cmp_hex(stream, "FFA0B514647EC04F6306B892AE661181");
+
+    let mut writer = Rc4Writer::new_drop(&key40, 3072, Vec::new());
+    writer.write_all(&zeros).unwrap();
+    cmp_hex(&writer.into_inner()[..], "EC0E11C479DC329DC8DA7968FE965681");
+
+    assert!(Rc4::try_new_drop(b"", 768).is_err());
+  }
+
+  #[test]
+  fn test_skip() {
+    let key = from_hex("0102030405060708090A0B0C0D0E0F10");
+    let mut rc4 = Rc4::new(&key);
+    assert_eq!(rc4.position(), 0);
+
+    let mut buf = [0u8; 16];
+    rc4.read_exact(&mut buf).unwrap();
+    assert_eq!(rc4.position(), 16);
+
+    rc4.skip(1520).unwrap();
+    assert_eq!(rc4.position(), 1536);
+    cmp_hex(&mut rc4, "FFA0B514647EC04F6306B892AE661181");
+    assert_eq!(rc4.position(), 1552);
+
+    let rc4 = Rc4::new_drop(&key, 3072);
+    assert_eq!(rc4.position(), 3072);
+    cmp_hex(rc4, "C05D88ABD50357F935A63C59EE537623");
+  }
+
+  #[test]
+  fn test_apply_keystream() {
+    let mut buf = *b"Attack at dawn";
+    let mut rc4 = Rc4::new(b"Secret");
+    rc4.apply_keystream(&mut buf);
+    cmp_hex(&buf[..], "45A01F645FC35B383552544B9BF5");
+    assert_eq!(rc4.position(), 14);
+
+    let mut rc4 = Rc4::new(b"Secret");
+    rc4.apply_keystream(&mut buf[..5]);
+    rc4.apply_keystream(&mut buf[5..]);
+    assert_eq!(&buf, b"Attack at dawn");
+  }
+
+  #[test]
+  fn test_debug_redacted() {
+    let rc4 = Rc4::new(b"Key");
+    assert_eq!(format!("{:?}", rc4), "Rc4 { .. }");
+
+    let key = Rc4Key::new(b"Key").unwrap();
+    assert_eq!(format!("{:?}", key), "Rc4Key { .. }");
+  }
+
+  #[test]
+  fn test_read_chunked() {
+    // Keystream read in odd-sized pieces must match one large read.
+    let mut whole = vec![0u8; 4099];
+    Rc4::new(b"Key").read_exact(&mut whole).unwrap();
+
+    let mut rc4 = Rc4::new(b"Key");
+    let mut pieces = vec![0u8; whole.len()];
+    for chunk in pieces.chunks_mut(7) {
+      rc4.read_exact(chunk).unwrap();
+    }
+
+    assert_eq!(pieces, whole);
+  }
+
+  #[test]
+  fn test_position_overflow() {
+    let mut rc4 = Rc4::new(b"Key");
+    rc4.skip(16).unwrap();
+    assert!(matches!(rc4.skip(u64::MAX), Err(Error::KeystreamExhausted)));
+    assert_eq!(rc4.position(), 16);
+
+    rc4.pos = u64::MAX - 1;
+    let mut buf = [0u8; 2];
+    assert!(matches!(rc4.try_apply_keystream(&mut buf), Err(Error::KeystreamExhausted)));
+    assert_eq!(buf, [0, 0]);
+
+    let err = rc4.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+  }
+
+  #[test]
+  fn test_data_interrupted() {
+    let plain = b"Attack at dawn";
+    let mut stream = Rc4Reader::new(b"Secret", Hiccup { data: &plain[..], fail: true });
+    let mut buf = [0u8; 14];
+    let mut num = 0;
+
+    assert_eq!(stream.read(&mut buf[..0]).unwrap(), 0);
+
+    while num < buf.len() {
+      match stream.read(&mut buf[num..]) {
+        Ok(n) => num += n,
+        Err(e) => assert_eq!(e.kind(), io::ErrorKind::Interrupted)
+      }
+    }
+
+    cmp_hex(&buf[..], "45A01F645FC35B383552544B9BF5");
+    assert_eq!(stream.read_to_end(&mut Vec::new()).unwrap(), 0);
+    assert_eq!(stream.keystream().position(), 14);
+  }
+
+  #[test]
+  fn test_data() {
+    test_rc4_data("Key", "Plaintext", "BBF316E8D940AF0AD3");
+    test_rc4_data("Wiki", "pedia", "1021BF0420");
+    test_rc4_data("Secret", "Attack at dawn", "45A01F645FC35B383552544B9BF5");
+  }
+
+  #[test]
+  fn test_data_decrypt() {
+    test_rc4_data_decrypt("Key", "Plaintext");
+    test_rc4_data_decrypt("Wiki", "pedia");
+    test_rc4_data_decrypt("Secret", "Attack at dawn");
+  }
+
+  #[test]
+  fn test_writer() {
+    test_rc4_writer("Key", "Plaintext", "BBF316E8D940AF0AD3");
+    test_rc4_writer("Wiki", "pedia", "1021BF0420");
+    test_rc4_writer("Secret", "Attack at dawn", "45A01F645FC35B383552544B9BF5");
+  }
+
+  #[test]
+  fn test_writer_partial() {
+    let mut writer = Rc4Writer::new(b"Secret", Trickle(Vec::new()));
+    writer.write_all(b"Attack at dawn").unwrap();
+    let buf = writer.into_inner().0;
+    cmp_hex(&buf[..], "45A01F645FC35B383552544B9BF5");
+  }
+
+  #[test]
+  fn test_writer_copy() {
+    let plain = vec![0x5a; 10000];
+    let mut writer = Rc4Writer::new(b"Key", Vec::new());
+    io::copy(&mut &plain[..], &mut writer).unwrap();
+    writer.flush().unwrap();
+
+    let cipher = writer.into_inner();
+    let mut reader = Rc4Reader::new(b"Key", &cipher[..]);
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, plain);
+  }
+
+  fn test_rc4_raw(key: &str, hex: &str) {
+    let stream = Rc4::new(key.as_bytes());
+    cmp_hex(stream, hex);
+  }
+
+  fn test_rc4_data(key: &str, data: &str, hex: &str) {
+    let stream = Rc4Reader::new(key.as_bytes(), data.as_bytes());
+    cmp_hex(stream, hex);
+  }
+
+  fn test_rc4_data_decrypt(key: &str, plain: &str) {
+    let estream = Rc4Reader::new(key.as_bytes(), plain.as_bytes());
+    let mut dstream = Rc4Reader::new(key.as_bytes(), estream);
+    let mut buf = vec![0; plain.len()];
+    dstream.read_exact(&mut buf).unwrap();
+    assert_eq!(from_utf8(&buf).unwrap(), plain);
+  }
+
+  fn test_rc4_writer(key: &str, data: &str, hex: &str) {
+    let mut writer = Rc4Writer::new(key.as_bytes(), Vec::new());
+    writer.write_all(data.as_bytes()).unwrap();
+    cmp_hex(&writer.into_inner()[..], hex);
+  }
+
+  // Fails every other read with `Interrupted`, and returns at most
+  // four bytes otherwise.
+  struct Hiccup<'a> {
+    data: &'a [u8],
+    fail: bool
+  }
+
+  impl Read for Hiccup<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+      self.fail = !self.fail;
+
+      if self.fail {
+        return Err(io::ErrorKind::Interrupted.into());
+      }
+
+      let len = buf.len().min(4);
+      self.data.read(&mut buf[..len])
+    }
+  }
+
+  // Accepts at most three bytes per write.
+  struct Trickle(Vec<u8>);
+
+  impl Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+      let num = buf.len().min(3);
+      self.0.extend_from_slice(&buf[..num]);
+      Ok(num)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  fn cmp_hex<R: Read>(mut reader: R, hex: &str) {
+    let mut buf = vec![0; hex.len() / 2];
+    reader.read_exact(&mut buf).unwrap();
+    let result = buf.iter().fold(String::new(), |a, &b| format!("{}{:02X}", a, b));
+    assert_eq!(result, hex);
+  }
+}
@@ -0,0 +1,350 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Files compatible with `openssl enc -rc4` and `openssl enc -rc4-40`.
+//!
+//! A salted file starts with `Salted__` and an 8-byte salt, followed by
+//! the RC4-encrypted data. The key is derived from the password and
+//! salt with `EVP_BytesToKey` (one iteration) or, with `-pbkdf2`,
+//! PBKDF2-HMAC. Files written with `-nosalt` have no header at all.
+//!
+//! OpenSSL 3 hashes with SHA-256 unless told otherwise, older versions
+//! with MD5, so pass `-md` explicitly when exchanging files.
+
+use std::io::{Read, Write};
+
+use md5::Md5;
+use sha2::Sha256;
+
+use crate::{key, wipe, Error, Rc4, Rc4Key, Rc4Reader, Rc4Writer, Result};
+
+/// The magic bytes at the start of every salted file.
+pub const MAGIC: &[u8; 8] = b"Salted__";
+
+/// The length of the salt following `MAGIC`.
+pub const SALT_LEN: usize = 8;
+
+/// The length of the `Salted__` header including the salt.
+pub const HEADER_LEN: usize = MAGIC.len() + SALT_LEN;
+
+/// The iteration count `openssl enc -pbkdf2` uses without `-iter`.
+pub const PBKDF2_DEFAULT_ITERATIONS: u32 = 10000;
+
+/// The OpenSSL cipher name, which determines the key length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cipher {
+  /// `rc4`, with a 128-bit key.
+  Rc4,
+  /// `rc4-40`, with a 40-bit key.
+  Rc4With40BitKey
+}
+
+impl Cipher {
+  /// Returns the key length in bytes.
+  pub fn key_len(self) -> usize {
+    match self {
+      Cipher::Rc4 => 16,
+      Cipher::Rc4With40BitKey => 5
+    }
+  }
+}
+
+/// The message digest selected with `-md`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Digest {
+  Md5,
+  Sha256
+}
+
+/// How the key is derived from the password.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kdf {
+  /// `EVP_BytesToKey` with a single iteration, the `openssl enc`
+  /// default.
+  BytesToKey,
+  /// PBKDF2-HMAC, selected with `-pbkdf2` and `-iter`.
+  Pbkdf2 { iterations: u32 }
+}
+
+/// The `openssl enc` options that affect the file format.
+///
+/// The default matches `openssl enc -rc4 -md md5`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Options {
+  pub cipher: Cipher,
+  pub digest: Digest,
+  pub kdf: Kdf,
+  /// Whether the file has a `Salted__` header; false for `-nosalt`.
+  pub salted: bool
+}
+
+impl Default for Options {
+  fn default() -> Options {
+    Options { cipher: Cipher::Rc4, digest: Digest::Md5, kdf: Kdf::BytesToKey, salted: true }
+  }
+}
+
+/// Derives the RC4 key from `password` and `salt` the way OpenSSL does.
+/// `salt` is empty for unsalted files.
+pub fn derive_key(password: &[u8], salt: &[u8], opts: &Options) -> Result<Rc4Key> {
+  let mut key = vec![0u8; opts.cipher.key_len()];
+
+  match (opts.kdf, opts.digest) {
+    (Kdf::BytesToKey, Digest::Md5) => bytes_to_key::<Md5>(password, salt, &mut key),
+    (Kdf::BytesToKey, Digest::Sha256) => bytes_to_key::<Sha256>(password, salt, &mut key),
+    (Kdf::Pbkdf2 { iterations: 0 }, _) => {
+      return Err(Error::InvalidParameter("PBKDF2 needs at least one iteration"));
+    }
+    (Kdf::Pbkdf2 { iterations }, Digest::Md5) => {
+      pbkdf2::pbkdf2_hmac::<Md5>(password, salt, iterations, &mut key)
+    }
+    (Kdf::Pbkdf2 { iterations }, Digest::Sha256) => {
+      pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, &mut key)
+    }
+  }
+
+  Rc4Key::from_vec(key)
+}
+
+/// Encrypts `msg`, with a random salt unless `opts.salted` is false.
+pub fn encrypt(password: &[u8], msg: &[u8], opts: &Options) -> Result<Vec<u8>> {
+  let salt = random_salt(opts)?;
+  seal(password, salt.as_ref(), msg, opts)
+}
+
+/// Encrypts `msg` with the given salt, which requires `opts.salted`.
+///
+/// Never reuse a salt with the same password; `encrypt` picks a random
+/// one.
+pub fn encrypt_with_salt(
+  password: &[u8],
+  salt: &[u8; SALT_LEN],
+  msg: &[u8],
+  opts: &Options
+) -> Result<Vec<u8>> {
+  if !opts.salted {
+    return Err(Error::InvalidParameter("a salt was given for an unsalted file"));
+  }
+
+  seal(password, Some(salt), msg, opts)
+}
+
+/// Decrypts a whole file.
+pub fn decrypt(password: &[u8], file: &[u8], opts: &Options) -> Result<Vec<u8>> {
+  let (salt, body) = if opts.salted {
+    if file.len() < HEADER_LEN {
+      return Err(Error::Format("OpenSSL file is shorter than its header"));
+    }
+
+    let (header, body) = file.split_at(HEADER_LEN);
+    (parse_header(header)?, body)
+  } else {
+    (&[][..], file)
+  };
+
+  let mut rc4 = Rc4::from_key(&derive_key(password, salt, opts)?);
+  let mut out = body.to_vec();
+  rc4.apply_keystream(&mut out);
+  Ok(out)
+}
+
+/// Writes the header to `inner`, if any, and returns a writer that
+/// encrypts everything written to it after that.
+pub fn writer<W: Write>(password: &[u8], opts: &Options, mut inner: W) -> Result<Rc4Writer<W>> {
+  let salt = random_salt(opts)?;
+  let salt = salt.as_ref().map_or(&[][..], |s| &s[..]);
+  let rc4 = Rc4::from_key(&derive_key(password, salt, opts)?);
+
+  if opts.salted {
+    inner.write_all(MAGIC)?;
+    inner.write_all(salt)?;
+  }
+
+  Ok(Rc4Writer::from_keystream(rc4, inner))
+}
+
+/// Reads the header from `inner`, if any, and returns a reader that
+/// decrypts the rest of the file.
+pub fn reader<R: Read>(password: &[u8], opts: &Options, mut inner: R) -> Result<Rc4Reader<R>> {
+  let mut header = [0u8; HEADER_LEN];
+  let salt = if opts.salted {
+    inner.read_exact(&mut header)?;
+    parse_header(&header)?
+  } else {
+    &[]
+  };
+
+  let rc4 = Rc4::from_key(&derive_key(password, salt, opts)?);
+  Ok(Rc4Reader::from_keystream(rc4, inner))
+}
+
+fn seal(
+  password: &[u8],
+  salt: Option<&[u8; SALT_LEN]>,
+  msg: &[u8],
+  opts: &Options
+) -> Result<Vec<u8>> {
+  let mut rc4 = Rc4::from_key(&derive_key(password, salt.map_or(&[], |s| &s[..]), opts)?);
+
+  let mut out = Vec::with_capacity(HEADER_LEN + msg.len());
+  if let Some(salt) = salt {
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(salt);
+  }
+
+  let start = out.len();
+  out.extend_from_slice(msg);
+  rc4.apply_keystream(&mut out[start..]);
+  Ok(out)
+}
+
+// Returns the salt from a `HEADER_LEN`-byte header.
+fn parse_header(header: &[u8]) -> Result<&[u8]> {
+  if &header[..MAGIC.len()] != MAGIC {
+    return Err(Error::Format("missing Salted__ header"));
+  }
+
+  Ok(&header[MAGIC.len()..])
+}
+
+fn random_salt(opts: &Options) -> Result<Option<[u8; SALT_LEN]>> {
+  if !opts.salted {
+    return Ok(None);
+  }
+
+  let mut salt = [0u8; SALT_LEN];
+  key::random(&mut salt)?;
+  Ok(Some(salt))
+}
+
+// EVP_BytesToKey with one iteration: D_1 = H(password || salt),
+// D_n = H(D_n-1 || password || salt), concatenated until `out` is full.
+fn bytes_to_key<D: sha2::digest::Digest>(password: &[u8], salt: &[u8], out: &mut [u8]) {
+  let mut prev = Vec::new();
+
+  for chunk in out.chunks_mut(<D as sha2::digest::Digest>::output_size()) {
+    let mut h = D::new();
+    h.update(&prev);
+    h.update(password);
+    h.update(salt);
+
+    wipe::wipe(&mut prev);
+    prev = h.finalize().to_vec();
+    chunk.copy_from_slice(&prev[..chunk.len()]);
+  }
+
+  wipe::wipe(&mut prev);
+}
+
+#[cfg(test)]
+mod test {
+  use crate::openssl::{self, Cipher, Digest, Kdf, Options, HEADER_LEN, SALT_LEN};
+  use crate::test_util::to_hex;
+  use crate::Error;
+  use std::io::{Read, Write};
+
+  const PASSWORD: &[u8] = b"correct horse";
+  const PLAIN: &[u8] = include_bytes!("../testdata/openssl/plain.txt");
+
+  // Files written by `openssl enc`, see testdata/openssl/README.
+  const FIXTURES: &[(&[u8], Options)] = &[
+    (
+      include_bytes!("../testdata/openssl/rc4-md5.enc"),
+      Options { cipher: Cipher::Rc4, digest: Digest::Md5, kdf: Kdf::BytesToKey, salted: true }
+    ),
+    (
+      include_bytes!("../testdata/openssl/rc4-40-md5.enc"),
+      Options {
+        cipher: Cipher::Rc4With40BitKey,
+        digest: Digest::Md5,
+        kdf: Kdf::BytesToKey,
+        salted: true
+      }
+    ),
+    (
+      include_bytes!("../testdata/openssl/rc4-sha256.enc"),
+      Options { cipher: Cipher::Rc4, digest: Digest::Sha256, kdf: Kdf::BytesToKey, salted: true }
+    ),
+    (
+      include_bytes!("../testdata/openssl/rc4-pbkdf2.enc"),
+      Options {
+        cipher: Cipher::Rc4,
+        digest: Digest::Sha256,
+        kdf: Kdf::Pbkdf2 { iterations: 1000 },
+        salted: true
+      }
+    ),
+    (
+      include_bytes!("../testdata/openssl/rc4-nosalt.enc"),
+      Options { cipher: Cipher::Rc4, digest: Digest::Md5, kdf: Kdf::BytesToKey, salted: false }
+    )
+  ];
+
+  #[test]
+  fn test_fixtures() {
+    for (file, opts) in FIXTURES {
+      assert_eq!(openssl::decrypt(PASSWORD, file, opts).unwrap(), PLAIN, "{:?}", opts);
+
+      let mut reader = openssl::reader(PASSWORD, opts, &file[..]).unwrap();
+      let mut buf = Vec::new();
+      reader.read_to_end(&mut buf).unwrap();
+      assert_eq!(buf, PLAIN, "{:?}", opts);
+    }
+  }
+
+  #[test]
+  fn test_encrypt_matches_openssl() {
+    for (file, opts) in FIXTURES.iter().filter(|(_, opts)| opts.salted) {
+      let salt: [u8; SALT_LEN] = file[8..HEADER_LEN].try_into().unwrap();
+      let out = openssl::encrypt_with_salt(PASSWORD, &salt, PLAIN, opts).unwrap();
+      assert_eq!(&out[..], &file[..], "{:?}", opts);
+    }
+
+    let (file, opts) = &FIXTURES[4];
+    assert_eq!(&openssl::encrypt(PASSWORD, PLAIN, opts).unwrap()[..], &file[..]);
+  }
+
+  #[test]
+  fn test_derive_key() {
+    // `openssl enc -rc4 -md md5 -S 73616c7479303031 -P`
+    let opts = Options::default();
+    let key = openssl::derive_key(PASSWORD, b"salty001", &opts).unwrap();
+    assert_eq!(to_hex(key.as_bytes()), "e6deacd0a252b4610e76ec7251c890af");
+
+    let opts = Options { cipher: Cipher::Rc4With40BitKey, ..opts };
+    let key = openssl::derive_key(PASSWORD, b"salty001", &opts).unwrap();
+    assert_eq!(to_hex(key.as_bytes()), "e6deacd0a2");
+  }
+
+  #[test]
+  fn test_writer() {
+    for (_, opts) in FIXTURES {
+      let mut writer = openssl::writer(PASSWORD, opts, Vec::new()).unwrap();
+      writer.write_all(PLAIN).unwrap();
+
+      let file = writer.into_inner();
+      assert_eq!(openssl::decrypt(PASSWORD, &file, opts).unwrap(), PLAIN);
+    }
+  }
+
+  #[test]
+  fn test_errors() {
+    let opts = Options::default();
+    let (file, _) = FIXTURES[0];
+
+    assert!(matches!(openssl::decrypt(PASSWORD, &file[..15], &opts), Err(Error::Format(_))));
+    assert!(matches!(openssl::decrypt(PASSWORD, &file[1..], &opts), Err(Error::Format(_))));
+    assert!(matches!(openssl::reader(PASSWORD, &opts, &file[..15]), Err(Error::Io(_))));
+    assert!(matches!(openssl::reader(PASSWORD, &opts, &file[1..]), Err(Error::Format(_))));
+
+    let unsalted = Options { salted: false, ..opts };
+    assert!(matches!(
+      openssl::encrypt_with_salt(PASSWORD, b"salty001", b"", &unsalted),
+      Err(Error::InvalidParameter(_))
+    ));
+
+    let pbkdf2 = Options { kdf: Kdf::Pbkdf2 { iterations: 0 }, ..opts };
+    assert!(matches!(openssl::encrypt(PASSWORD, b"", &pbkdf2), Err(Error::InvalidParameter(_))));
+  }
+}
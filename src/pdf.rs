@@ -0,0 +1,366 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The PDF Standard Security Handler, revisions 2 to 4, with RC4.
+//!
+//! A file encryption key is computed from a user or owner password and
+//! the document's `/Encrypt` dictionary and checked against its `/U`
+//! entry (ISO 32000-1, 7.6.3). Strings and streams in object `num` of
+//! generation `gen` are then encrypted with RC4 keyed with the first
+//! `n + 5` bytes, at most 16, of `MD5(key || num || gen)`.
+//!
+//! Parsing the document is left to the caller, which hands over the
+//! relevant values of the `/Encrypt` dictionary and the trailer's `/ID`.
+//! Revision 4 documents may use AES crypt filters instead of RC4; only
+//! the `/V2` crypt filter is supported, and objects whose filter is
+//! `/Identity` must not be decrypted.
+
+use std::fmt;
+use std::io::Read;
+
+use md5::{Digest, Md5};
+
+use crate::{ct, wipe, Error, Rc4, Rc4Reader, Result};
+
+/// The bytes short passwords are padded with.
+pub const PADDING: [u8; 32] = [
+  0x28, 0xbf, 0x4e, 0x5e, 0x4e, 0x75, 0x8a, 0x41, 0x64, 0x00, 0x4e, 0x56, 0xff, 0xfa, 0x01, 0x08,
+  0x2e, 0x2e, 0x00, 0xb6, 0xd0, 0x68, 0x3e, 0x80, 0x2f, 0x0c, 0xa9, 0xfe, 0x64, 0x53, 0x69, 0x7a
+];
+
+/// The length of the `/O` and `/U` entries.
+pub const ENTRY_LEN: usize = 32;
+
+/// The values of an `/Encrypt` dictionary the handler needs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncryptDict {
+  /// `/R`, from 2 to 4.
+  pub revision: u8,
+  /// `/Length`, the key length in bits; revision 2 always uses 40.
+  pub key_bits: u32,
+  /// `/O`, derived from both passwords.
+  pub owner: Vec<u8>,
+  /// `/U`, used to check the user password.
+  pub user: Vec<u8>,
+  /// `/P`, the permission flags.
+  pub permissions: i32,
+  /// `/EncryptMetadata`, which only revision 4 honours.
+  pub encrypt_metadata: bool,
+  /// The first string of the trailer's `/ID` array.
+  pub id: Vec<u8>
+}
+
+impl EncryptDict {
+  // Returns the file key length in bytes.
+  fn key_len(&self) -> Result<usize> {
+    match (self.revision, self.key_bits) {
+      (2, _) => Ok(5),
+      (3 | 4, bits) if (40..=128).contains(&bits) && bits.is_multiple_of(8) => {
+        Ok(bits as usize / 8)
+      }
+      (3 | 4, _) => Err(Error::Format("bad PDF key length")),
+      _ => Err(Error::Format("unsupported Standard Security Handler revision"))
+    }
+  }
+
+  fn check(&self) -> Result<usize> {
+    let len = self.key_len()?;
+    if self.owner.len() != ENTRY_LEN || self.user.len() != ENTRY_LEN {
+      return Err(Error::Format("bad /O or /U entry"));
+    }
+
+    Ok(len)
+  }
+}
+
+/// A document's file encryption key.
+///
+/// The key bytes are zeroized on drop and redacted from `Debug`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct FileKey(Vec<u8>);
+
+impl FileKey {
+  /// Computes the key from `password`, trying it as the user password
+  /// and then as the owner password.
+  pub fn from_password(dict: &EncryptDict, password: &[u8]) -> Result<FileKey> {
+    match FileKey::from_user_password(dict, password) {
+      Err(Error::AuthenticationFailed) => FileKey::from_owner_password(dict, password),
+      result => result
+    }
+  }
+
+  /// Computes the key from the user password and checks it against
+  /// `/U`. Many documents have an empty user password.
+  pub fn from_user_password(dict: &EncryptDict, password: &[u8]) -> Result<FileKey> {
+    let len = dict.check()?;
+    let key = FileKey(file_key(dict, len, password));
+
+    let entry = user_entry(dict, &key.0);
+    let n = if dict.revision == 2 { ENTRY_LEN } else { 16 };
+    if !ct::eq(&entry[..n], &dict.user[..n]) {
+      return Err(Error::AuthenticationFailed);
+    }
+
+    Ok(key)
+  }
+
+  /// Recovers the user password from `/O` with the owner password and
+  /// computes the key from it.
+  pub fn from_owner_password(dict: &EncryptDict, password: &[u8]) -> Result<FileKey> {
+    let len = dict.check()?;
+
+    let mut hash = Md5::digest(pad(password));
+    if dict.revision >= 3 {
+      for _ in 0..50 {
+        hash = Md5::digest(hash);
+      }
+    }
+
+    let mut user = dict.owner.clone();
+    if dict.revision == 2 {
+      Rc4::new(&hash[..len]).apply_keystream(&mut user);
+    } else {
+      for i in (0..20).rev() {
+        rc4_xor(&hash[..len], i).apply_keystream(&mut user);
+      }
+    }
+
+    let result = FileKey::from_user_password(dict, &user);
+    wipe::wipe(&mut user);
+    wipe::wipe(&mut hash);
+    result
+  }
+
+  /// Returns the key length in bytes, 5 to 16.
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  /// Returns true if the key is empty, which never happens.
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  /// Decrypts a string in object `num` of generation `gen`.
+  pub fn decrypt_string(&self, num: u32, gen: u16, data: &[u8]) -> Vec<u8> {
+    let mut out = data.to_vec();
+    self.object_keystream(num, gen).apply_keystream(&mut out);
+    out
+  }
+
+  /// Decrypts the data of stream object `num` of generation `gen`,
+  /// before any of its `/Filter`s are applied.
+  pub fn decrypt_stream(&self, num: u32, gen: u16, data: &[u8]) -> Vec<u8> {
+    self.decrypt_string(num, gen, data)
+  }
+
+  /// Wraps the data of stream object `num` of generation `gen` to
+  /// decrypt it while reading.
+  pub fn stream_reader<R: Read>(&self, num: u32, gen: u16, inner: R) -> Rc4Reader<R> {
+    Rc4Reader::from_keystream(self.object_keystream(num, gen), inner)
+  }
+
+  fn object_keystream(&self, num: u32, gen: u16) -> Rc4 {
+    let mut hash = Md5::new()
+      .chain_update(&self.0)
+      .chain_update(&num.to_le_bytes()[..3])
+      .chain_update(gen.to_le_bytes())
+      .finalize();
+
+    let rc4 = Rc4::new(&hash[..(self.0.len() + 5).min(16)]);
+    wipe::wipe(&mut hash);
+    rc4
+  }
+}
+
+impl Drop for FileKey {
+  fn drop(&mut self) {
+    wipe::wipe(&mut self.0);
+  }
+}
+
+impl fmt::Debug for FileKey {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("FileKey").finish_non_exhaustive()
+  }
+}
+
+fn pad(password: &[u8]) -> [u8; 32] {
+  let mut out = [0u8; 32];
+  let len = password.len().min(32);
+  out[..len].copy_from_slice(&password[..len]);
+  out[len..].copy_from_slice(&PADDING[..32 - len]);
+  out
+}
+
+// Algorithm 2 in ISO 32000-1.
+fn file_key(dict: &EncryptDict, len: usize, password: &[u8]) -> Vec<u8> {
+  let mut md5 = Md5::new()
+    .chain_update(pad(password))
+    .chain_update(&dict.owner)
+    .chain_update(dict.permissions.to_le_bytes())
+    .chain_update(&dict.id);
+  if dict.revision >= 4 && !dict.encrypt_metadata {
+    md5.update([0xff; 4]);
+  }
+
+  let mut hash = md5.finalize();
+  if dict.revision >= 3 {
+    for _ in 0..50 {
+      hash = Md5::digest(&hash[..len]);
+    }
+  }
+
+  let key = hash[..len].to_vec();
+  wipe::wipe(&mut hash);
+  key
+}
+
+// Algorithms 4 and 5; only the first 16 bytes matter for revision 3
+// and later.
+fn user_entry(dict: &EncryptDict, key: &[u8]) -> [u8; ENTRY_LEN] {
+  let mut entry = [0u8; ENTRY_LEN];
+
+  if dict.revision == 2 {
+    entry = PADDING;
+    Rc4::new(key).apply_keystream(&mut entry);
+  } else {
+    let hash = Md5::new().chain_update(PADDING).chain_update(&dict.id).finalize();
+    entry[..16].copy_from_slice(&hash);
+    for i in 0..20 {
+      rc4_xor(key, i).apply_keystream(&mut entry[..16]);
+    }
+  }
+
+  entry
+}
+
+// Keys RC4 with every byte of `key` XORed with `i`.
+fn rc4_xor(key: &[u8], i: u8) -> Rc4 {
+  let mut tmp = [0u8; 16];
+  for (t, k) in tmp.iter_mut().zip(key) {
+    *t = k ^ i;
+  }
+
+  let rc4 = Rc4::new(&tmp[..key.len()]);
+  wipe::wipe(&mut tmp);
+  rc4
+}
+
+#[cfg(test)]
+mod test {
+  use std::io::Read;
+
+  use crate::pdf::{EncryptDict, FileKey};
+  use crate::test_util::from_hex;
+  use crate::Error;
+
+  // Written by testdata/pdf/make_fixtures.py, an independent Python
+  // implementation of the handler.
+  const REV2_40: &[u8] = include_bytes!("../testdata/pdf/rev2-40.pdf");
+  const REV3_128: &[u8] = include_bytes!("../testdata/pdf/rev3-128.pdf");
+  const REV3_56: &[u8] = include_bytes!("../testdata/pdf/rev3-56.pdf");
+  const REV4_128: &[u8] = include_bytes!("../testdata/pdf/rev4-128.pdf");
+
+  const TITLE: &[u8] = b"Encrypted sample";
+  const CONTENT: &[u8] = b"BT /F1 12 Tf 20 100 Td (Hello, RC4!) Tj ET";
+
+  fn find(pdf: &[u8], needle: &[u8]) -> usize {
+    pdf.windows(needle.len()).position(|w| w == needle).unwrap() + needle.len()
+  }
+
+  // Returns the hex string following `needle`.
+  fn hex_after(pdf: &[u8], needle: &[u8]) -> Vec<u8> {
+    let start = find(pdf, needle);
+    let len = pdf[start..].iter().position(|&b| b == b'>').unwrap();
+    from_hex(std::str::from_utf8(&pdf[start..start + len]).unwrap())
+  }
+
+  // The revision, key length and permissions come from the caller.
+  fn dict(pdf: &[u8], revision: u8, key_bits: u32, permissions: i32) -> EncryptDict {
+    EncryptDict {
+      revision,
+      key_bits,
+      owner: hex_after(pdf, b"/O <"),
+      user: hex_after(pdf, b"/U <"),
+      permissions,
+      encrypt_metadata: !pdf.windows(22).any(|w| w == b"/EncryptMetadata false"),
+      id: hex_after(pdf, b"/ID [<")
+    }
+  }
+
+  fn stream(pdf: &[u8]) -> &[u8] {
+    let start = find(pdf, b"stream\n");
+    &pdf[start..start + CONTENT.len()]
+  }
+
+  fn check(pdf: &[u8], key: &FileKey) {
+    assert_eq!(key.decrypt_string(5, 0, &hex_after(pdf, b"/Title <")), TITLE);
+    assert_eq!(key.decrypt_stream(4, 0, stream(pdf)), CONTENT);
+  }
+
+  #[test]
+  fn test_fixtures() {
+    for (pdf, revision, bits, permissions, user, owner) in [
+      (REV2_40, 2, 40, -44, &b"user"[..], &b"owner"[..]),
+      (REV3_128, 3, 128, -3904, b"", b"owner secret"),
+      (REV3_56, 3, 56, -4, b"pw", b"pw"),
+      (REV4_128, 4, 128, -1028, b"user4", b"owner4")
+    ] {
+      let dict = dict(pdf, revision, bits, permissions);
+
+      let key = FileKey::from_user_password(&dict, user).unwrap();
+      assert_eq!(key.len() as u32 * 8, bits);
+      check(pdf, &key);
+
+      let key = FileKey::from_owner_password(&dict, owner).unwrap();
+      check(pdf, &key);
+
+      assert_eq!(FileKey::from_password(&dict, owner).unwrap(), key);
+      assert_eq!(FileKey::from_password(&dict, user).unwrap(), key);
+    }
+  }
+
+  #[test]
+  fn test_stream_reader() {
+    let dict = dict(REV3_128, 3, 128, -3904);
+    let key = FileKey::from_password(&dict, b"").unwrap();
+
+    let mut out = Vec::new();
+    key.stream_reader(4, 0, stream(REV3_128)).read_to_end(&mut out).unwrap();
+    assert_eq!(out, CONTENT);
+    assert_ne!(key.decrypt_stream(4, 1, stream(REV3_128)), CONTENT);
+  }
+
+  #[test]
+  fn test_wrong_password() {
+    let dict = dict(REV2_40, 2, 40, -44);
+    let result = FileKey::from_password(&dict, b"guess");
+    assert!(matches!(result, Err(Error::AuthenticationFailed)));
+    let result = FileKey::from_user_password(&dict, b"owner");
+    assert!(matches!(result, Err(Error::AuthenticationFailed)));
+
+    // Changing the permissions changes the key.
+    let dict = EncryptDict { permissions: -1, ..dict };
+    let result = FileKey::from_user_password(&dict, b"user");
+    assert!(matches!(result, Err(Error::AuthenticationFailed)));
+  }
+
+  #[test]
+  fn test_errors() {
+    let dict = dict(REV4_128, 4, 128, -1028);
+
+    for bad in [
+      EncryptDict { revision: 5, ..dict.clone() },
+      EncryptDict { key_bits: 44, ..dict.clone() },
+      EncryptDict { key_bits: 256, ..dict.clone() },
+      EncryptDict { user: vec![0; 16], ..dict.clone() }
+    ] {
+      assert!(matches!(FileKey::from_password(&bad, b"user4"), Err(Error::Format(_))));
+    }
+
+    let key = FileKey::from_password(&dict, b"user4").unwrap();
+    assert_eq!(format!("{:?}", key), "FileKey { .. }");
+  }
+}
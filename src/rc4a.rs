@@ -0,0 +1,227 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! RC4A, as proposed by Souradyuti Paul and Bart Preneel in "A New
+//! Weakness in the RC4 Keystream Generator and an Approach to Improve
+//! the Security of the Cipher" (FSE 2004).
+
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::{key, ksa, wipe, Error, KeystreamGenerator, Rc4, Result};
+
+/// The RC4A keystream.
+///
+/// RC4A keeps two permutations, `S1` and `S2`. `S1` is set up by the
+/// RC4 key schedule; the first 256 bytes of RC4 output from `S1` then
+/// key `S2`. Each step of `i` outputs two bytes: one from `S2` indexed
+/// by `S1`, then one from `S1` indexed by `S2`.
+///
+/// The paper only says that `S2` is keyed with output generated from
+/// `S1`. Here that output comes from a separate `Rc4` over the same
+/// key, so `S1` enters the RC4A rounds exactly as the key schedule left
+/// it, with `i` and `j1` at zero. Continuing `S1` from where the 256
+/// bytes ended would be another reading, with a different keystream.
+///
+/// Like `Rc4`, the state is zeroized on drop and redacted from `Debug`.
+#[derive(Clone)]
+pub struct Rc4A {
+  i: u8,
+  j1: u8,
+  j2: u8,
+  s1: [u8; 256],
+  s2: [u8; 256],
+  // Whether the next byte is the second of the current step.
+  odd: bool,
+  pos: u64
+}
+
+impl Rc4A {
+  /// Runs the key schedule over `key`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `key` is empty or longer than 256 bytes.
+  pub fn new(key: &[u8]) -> Rc4A {
+    match Rc4A::try_new(key) {
+      Ok(rc4a) => rc4a,
+      Err(e) => panic!("{}", e)
+    }
+  }
+
+  /// Runs the key schedule over `key`, rejecting keys that are empty
+  /// or longer than 256 bytes.
+  pub fn try_new(key: &[u8]) -> Result<Rc4A> {
+    key::check(key)?;
+
+    let s1 = ksa(key);
+    let mut k2 = [0u8; 256];
+    Rc4::new(key).apply_keystream(&mut k2);
+    let s2 = ksa(&k2);
+    wipe::wipe(&mut k2);
+
+    Ok(Rc4A { i: 0, j1: 0, j2: 0, s1, s2, odd: false, pos: 0 })
+  }
+
+  /// XORs the keystream into `buf` in place.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the keystream position would overflow a `u64`.
+  pub fn apply_keystream(&mut self, buf: &mut [u8]) {
+    if let Err(e) = self.try_apply_keystream(buf) {
+      panic!("{}", e);
+    }
+  }
+
+  /// XORs the keystream into `buf` in place, failing if the keystream
+  /// position would overflow a `u64`.
+  pub fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<()> {
+    self.pos = self.checked_pos(buf.len() as u64)?;
+
+    for b in buf.iter_mut() {
+      *b ^= self.next_byte();
+    }
+
+    Ok(())
+  }
+
+  /// Advances the keystream by `n` bytes without producing output.
+  pub fn skip(&mut self, n: u64) -> Result<()> {
+    self.pos = self.checked_pos(n)?;
+
+    for _ in 0..n {
+      self.next_byte();
+    }
+
+    Ok(())
+  }
+
+  /// Returns the number of keystream bytes consumed so far.
+  pub fn position(&self) -> u64 {
+    self.pos
+  }
+
+  fn checked_pos(&self, n: u64) -> Result<u64> {
+    self.pos.checked_add(n).ok_or(Error::KeystreamExhausted)
+  }
+
+  fn next_byte(&mut self) -> u8 {
+    let i = usize::from(self.i);
+    self.odd = !self.odd;
+
+    if self.odd {
+      let i = usize::from(self.i.wrapping_add(1));
+      self.i = i as u8;
+      self.j1 = self.j1.wrapping_add(self.s1[i]);
+      self.s1.swap(i, usize::from(self.j1));
+
+      let t = self.s1[i].wrapping_add(self.s1[usize::from(self.j1)]);
+      self.s2[usize::from(t)]
+    } else {
+      self.j2 = self.j2.wrapping_add(self.s2[i]);
+      self.s2.swap(i, usize::from(self.j2));
+
+      let t = self.s2[i].wrapping_add(self.s2[usize::from(self.j2)]);
+      self.s1[usize::from(t)]
+    }
+  }
+}
+
+impl KeystreamGenerator for Rc4A {
+  fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<()> {
+    Rc4A::try_apply_keystream(self, buf)
+  }
+
+  fn skip(&mut self, n: u64) -> Result<()> {
+    Rc4A::skip(self, n)
+  }
+
+  fn position(&self) -> u64 {
+    self.pos
+  }
+}
+
+impl Drop for Rc4A {
+  fn drop(&mut self) {
+    wipe::wipe(&mut self.s1);
+    wipe::wipe(&mut self.s2);
+    wipe::wipe_value(&mut self.i);
+    wipe::wipe_value(&mut self.j1);
+    wipe::wipe_value(&mut self.j2);
+    wipe::wipe_value(&mut self.pos);
+  }
+}
+
+impl fmt::Debug for Rc4A {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Rc4A").finish_non_exhaustive()
+  }
+}
+
+impl Read for Rc4A {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    buf.fill(0);
+    self.try_apply_keystream(buf)?;
+    Ok(buf.len())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::test_util::{from_hex, to_hex};
+  use crate::{KeystreamReader, KeystreamWriter, Rc4A};
+  use std::io::{Read, Write};
+
+  // Regression vectors; the paper doesn't publish any.
+  const VECTORS: &[(&str, &str)] = &[
+    ("0102030405", "f50e84657ca24913c9df3a0b5aad5bd87ee374b5c0a648f1e040d70ed4cf95aa"),
+    (
+      "0102030405060708090a0b0c0d0e0f10",
+      "4b8ea77a8a964be0e9796c6e5c4957b4712779cc9064634d6fba1e5699207636"
+    ),
+    (
+      "1ada31d5cf688221c109163908ebe51debb46227c6cc8b37641910833222772a",
+      "4c0ed7d9bd28bad296d30b6ceaf3ae3c5398e2e2d41f82e681602d12e6b23ff0"
+    )
+  ];
+
+  #[test]
+  fn test_rc4a() {
+    for &(key, hex) in VECTORS {
+      let mut buf = [0u8; 32];
+      Rc4A::new(&from_hex(key)).apply_keystream(&mut buf);
+      assert_eq!(to_hex(&buf), hex, "key {}", key);
+    }
+  }
+
+  #[test]
+  fn test_skip() {
+    let mut whole = [0u8; 32];
+    Rc4A::new(b"Key").apply_keystream(&mut whole);
+
+    // Skipping an odd number of bytes stops halfway through a step.
+    let mut rc4a = Rc4A::new(b"Key");
+    rc4a.skip(7).unwrap();
+    assert_eq!(rc4a.position(), 7);
+
+    let mut rest = [0u8; 25];
+    rc4a.read_exact(&mut rest).unwrap();
+    assert_eq!(rest, whole[7..]);
+  }
+
+  #[test]
+  fn test_data() {
+    let plain = b"Attack at dawn";
+    let mut writer = KeystreamWriter::from_keystream(Rc4A::new(b"Secret"), Vec::new());
+    writer.write_all(plain).unwrap();
+    let cipher = writer.into_inner();
+    assert_ne!(&cipher[..], &plain[..]);
+
+    let mut reader = KeystreamReader::from_keystream(Rc4A::new(b"Secret"), &cipher[..]);
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, plain);
+  }
+}
@@ -0,0 +1,248 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! RC4+, by Subhamoy Maitra and Goutam Paul, "Analysis of RC4 and
+//! Proposal of Additional Layers for Better Security Margin"
+//! (INDOCRYPT 2008).
+//!
+//! The paper publishes no test vectors and there is no reference
+//! implementation to compare against, so the tests here only guard
+//! against regressions. Their vectors come from this implementation.
+//! A separate Python implementation written from the paper produces
+//! the same output, but it is no more authoritative than this one.
+
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::ksa::{KeySchedule, Rc4PlusKsa};
+use crate::{identity, key, ksa_pass, wipe, Error, KeystreamGenerator, Result};
+
+/// The longest IV the RC4+ key schedule can make use of.
+pub const MAX_IV_LEN: usize = 128;
+
+/// The RC4+ keystream.
+///
+/// Keying runs three layers: the RC4 key schedule, an IV scrambling
+/// pass over both halves of the permutation, and a zig-zag pass that
+/// mixes the key in again. Each output byte combines three lookups
+/// instead of RC4's one.
+///
+/// The permutation is zeroized on drop and never shown by `Debug`.
+#[derive(Clone)]
+pub struct Rc4Plus {
+  i: u8,
+  j: u8,
+  state: [u8; 256],
+  pos: u64
+}
+
+impl Rc4Plus {
+  /// Runs the RC4+ key schedule over `key` with an all-zero IV.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `key` is empty or longer than 256 bytes.
+  pub fn new(key: &[u8]) -> Rc4Plus {
+    match Rc4Plus::with_iv(key, &[]) {
+      Ok(rc4p) => rc4p,
+      Err(e) => panic!("{}", e)
+    }
+  }
+
+  /// Runs the RC4+ key schedule over `key` and an IV of up to 128
+  /// bytes.
+  pub fn with_iv(key: &[u8], iv: &[u8]) -> Result<Rc4Plus> {
+    key::check(key)?;
+
+    let state = Rc4PlusKsa::new(iv)?.schedule(key);
+    Ok(Rc4Plus { i: 0, j: 0, state, pos: 0 })
+  }
+
+  /// XORs the keystream into `buf` in place.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the keystream position would overflow a `u64`.
+  pub fn apply_keystream(&mut self, buf: &mut [u8]) {
+    if let Err(e) = self.try_apply_keystream(buf) {
+      panic!("{}", e);
+    }
+  }
+
+  /// XORs the keystream into `buf` in place, failing if the keystream
+  /// position would overflow a `u64`.
+  pub fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<()> {
+    self.pos = self.checked_pos(buf.len() as u64)?;
+
+    for b in buf.iter_mut() {
+      *b ^= self.next_byte();
+    }
+
+    Ok(())
+  }
+
+  /// Advances the keystream by `n` bytes without producing output.
+  pub fn skip(&mut self, n: u64) -> Result<()> {
+    self.pos = self.checked_pos(n)?;
+
+    for _ in 0..n {
+      self.next_byte();
+    }
+
+    Ok(())
+  }
+
+  /// Returns the number of keystream bytes consumed so far.
+  pub fn position(&self) -> u64 {
+    self.pos
+  }
+
+  fn checked_pos(&self, n: u64) -> Result<u64> {
+    self.pos.checked_add(n).ok_or(Error::KeystreamExhausted)
+  }
+
+  fn next_byte(&mut self) -> u8 {
+    let s = &mut self.state;
+
+    self.i = self.i.wrapping_add(1);
+    let (i, j) = (self.i, self.j.wrapping_add(s[usize::from(self.i)]));
+    self.j = j;
+    s.swap(usize::from(i), usize::from(j));
+
+    let t = s[usize::from(i)].wrapping_add(s[usize::from(j)]);
+    let t1 = s[usize::from((i >> 3) ^ (j << 5))]
+      .wrapping_add(s[usize::from((i << 5) ^ (j >> 3))])
+      ^ 0xAA;
+    let t2 = j.wrapping_add(s[usize::from(j)]);
+
+    s[usize::from(t)].wrapping_add(s[usize::from(t1)]) ^ s[usize::from(t2)]
+  }
+}
+
+/// The three-layer RC4+ key schedule. `key` must not be empty and
+/// `iv` must be at most `MAX_IV_LEN` bytes.
+pub(crate) fn ksa_plus(key: &[u8], iv: &[u8]) -> [u8; 256] {
+  let k = |i: usize| key[i % key.len()];
+
+  // Layer 1 is the RC4 key schedule.
+  let mut s = identity();
+  let mut j = ksa_pass(&mut s, key, 0);
+
+  // Layer 2 places the IV symmetrically around the middle of the
+  // permutation and scrambles outwards from there.
+  let mut v = [0u8; 256];
+  for (n, &b) in iv.iter().enumerate() {
+    v[127 - n] = b;
+    v[128 + n] = b;
+  }
+
+  for i in (0..128).rev().chain(128..256) {
+    j = j.wrapping_add(s[i]) ^ k(i).wrapping_add(v[i]);
+    s.swap(i, usize::from(j));
+  }
+
+  wipe::wipe(&mut v);
+
+  // Layer 3 visits the permutation in zig-zag order: 0, 255, 1, 254...
+  for y in 0..256usize {
+    let i = if y % 2 == 0 { y / 2 } else { 256 - y.div_ceil(2) };
+    j = j.wrapping_add(s[i]).wrapping_add(k(i));
+    s.swap(i, usize::from(j));
+  }
+
+  s
+}
+
+impl KeystreamGenerator for Rc4Plus {
+  fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<()> {
+    Rc4Plus::try_apply_keystream(self, buf)
+  }
+
+  fn skip(&mut self, n: u64) -> Result<()> {
+    Rc4Plus::skip(self, n)
+  }
+
+  fn position(&self) -> u64 {
+    self.pos
+  }
+}
+
+impl Drop for Rc4Plus {
+  fn drop(&mut self) {
+    wipe::wipe(&mut self.state);
+    wipe::wipe_value(&mut self.i);
+    wipe::wipe_value(&mut self.j);
+    wipe::wipe_value(&mut self.pos);
+  }
+}
+
+impl fmt::Debug for Rc4Plus {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Rc4Plus").finish_non_exhaustive()
+  }
+}
+
+impl Read for Rc4Plus {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    buf.fill(0);
+    self.try_apply_keystream(buf)?;
+    Ok(buf.len())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::test_util::{from_hex, to_hex};
+  use crate::{Error, KeyError, KeystreamReader, Rc4Plus};
+  use std::io::Read;
+
+  // Regression vectors; see the module docs.
+  const VECTORS: &[(&str, &str, &str)] = &[
+    (
+      "0102030405",
+      "",
+      "356691048547029cf2ac1936b7e4c76beae4b06de92b0696d25326b3e247bcd1"
+    ),
+    (
+      "0102030405060708090a0b0c0d0e0f10",
+      "",
+      "9bfeed57cbff642f8f814a7e0c0b71bcdc9200c36a1aafea676c179bd91b5692"
+    ),
+    (
+      "0102030405060708090a0b0c0d0e0f10",
+      "f0e0d0c0b0a090807060504030201000",
+      "a4399e6180b1663dd0e60190ff30a595581f88102160bb52183e8b6a6bd41f47"
+    )
+  ];
+
+  #[test]
+  fn test_rc4plus() {
+    for &(key, iv, hex) in VECTORS {
+      let mut buf = [0u8; 32];
+      let mut rc4p = Rc4Plus::with_iv(&from_hex(key), &from_hex(iv)).unwrap();
+      rc4p.apply_keystream(&mut buf);
+      assert_eq!(to_hex(&buf), hex, "key {} iv {}", key, iv);
+    }
+  }
+
+  #[test]
+  fn test_bad_iv() {
+    assert!(matches!(
+      Rc4Plus::with_iv(b"Key", &[0; 129]),
+      Err(Error::Key(KeyError::IvTooLong { len: 129 }))
+    ));
+    assert!(Rc4Plus::with_iv(b"Key", &[0; 128]).is_ok());
+    assert!(Rc4Plus::with_iv(b"", b"iv").is_err());
+  }
+
+  #[test]
+  fn test_data() {
+    let key = from_hex("0102030405");
+    let zeros = [0u8; 32];
+    let mut reader = KeystreamReader::from_keystream(Rc4Plus::new(&key), &zeros[..]);
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(to_hex(&buf), VECTORS[0].2);
+  }
+}
@@ -0,0 +1,387 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Spritz, the sponge-like RC4 successor by Ronald L. Rivest and Jacob
+//! C. N. Schuldt, "Spritz - a spongy RC4-like stream cipher and hash
+//! function" (2014).
+//!
+//! `Spritz` exposes the raw sponge operations; the free functions build
+//! the hash, MAC, stream cipher and AEAD constructions from the paper
+//! on top of them.
+
+use std::fmt;
+
+use crate::{ct, wipe, Error, Result};
+
+const N: usize = 256;
+
+/// The length of the tags produced by `mac` and `aead_encrypt`.
+pub const TAG_LEN: usize = 32;
+
+// AEAD messages are absorbed in blocks of N/4 bytes.
+const AEAD_BLOCK: usize = N / 4;
+
+/// The Spritz sponge state.
+///
+/// The state is zeroized on drop and left out of `Debug` output.
+#[derive(Clone)]
+pub struct Spritz {
+  i: u8,
+  j: u8,
+  k: u8,
+  z: u8,
+  a: u8,
+  w: u8,
+  s: [u8; N]
+}
+
+impl Spritz {
+  /// Returns a freshly initialized state.
+  pub fn new() -> Spritz {
+    let mut s = [0u8; N];
+
+    for (v, x) in s.iter_mut().enumerate() {
+      *x = v as u8;
+    }
+
+    Spritz { i: 0, j: 0, k: 0, z: 0, a: 0, w: 1, s }
+  }
+
+  /// Returns a state that has absorbed `key`.
+  pub fn with_key(key: &[u8]) -> Spritz {
+    let mut spritz = Spritz::new();
+    spritz.absorb(key);
+    spritz
+  }
+
+  /// Absorbs `data` into the state.
+  pub fn absorb(&mut self, data: &[u8]) {
+    for &b in data {
+      self.absorb_nibble(b & 0x0f);
+      self.absorb_nibble(b >> 4);
+    }
+  }
+
+  /// Absorbs the special stop symbol, separating inputs.
+  pub fn absorb_stop(&mut self) {
+    if usize::from(self.a) == N / 2 {
+      self.shuffle();
+    }
+
+    self.a = self.a.wrapping_add(1);
+  }
+
+  /// Fills `out` with output bytes.
+  pub fn squeeze(&mut self, out: &mut [u8]) {
+    if self.a > 0 {
+      self.shuffle();
+    }
+
+    for b in out.iter_mut() {
+      *b = self.drip();
+    }
+  }
+
+  /// Returns a single output byte.
+  pub fn drip(&mut self) -> u8 {
+    if self.a > 0 {
+      self.shuffle();
+    }
+
+    self.update();
+    self.output()
+  }
+
+  /// XORs output bytes into `buf`, which encrypts or decrypts it.
+  pub fn apply_keystream(&mut self, buf: &mut [u8]) {
+    for b in buf.iter_mut() {
+      *b ^= self.drip();
+    }
+  }
+
+  fn absorb_nibble(&mut self, x: u8) {
+    if usize::from(self.a) == N / 2 {
+      self.shuffle();
+    }
+
+    self.s.swap(usize::from(self.a), N / 2 + usize::from(x));
+    self.a = self.a.wrapping_add(1);
+  }
+
+  fn shuffle(&mut self) {
+    self.whip(2 * N);
+    self.crush();
+    self.whip(2 * N);
+    self.crush();
+    self.whip(2 * N);
+    self.a = 0;
+  }
+
+  fn whip(&mut self, r: usize) {
+    for _ in 0..r {
+      self.update();
+    }
+
+    // w must stay relatively prime to N, i.e. odd.
+    self.w = self.w.wrapping_add(2);
+  }
+
+  fn crush(&mut self) {
+    for v in 0..N / 2 {
+      if self.s[v] > self.s[N - 1 - v] {
+        self.s.swap(v, N - 1 - v);
+      }
+    }
+  }
+
+  fn update(&mut self) {
+    let s = &mut self.s;
+
+    self.i = self.i.wrapping_add(self.w);
+    let si = s[usize::from(self.i)];
+    self.j = self.k.wrapping_add(s[usize::from(self.j.wrapping_add(si))]);
+    self.k = self.i.wrapping_add(self.k).wrapping_add(s[usize::from(self.j)]);
+    s.swap(usize::from(self.i), usize::from(self.j));
+  }
+
+  fn output(&mut self) -> u8 {
+    let s = &self.s;
+
+    let t = s[usize::from(self.z.wrapping_add(self.k))];
+    let t = s[usize::from(self.i.wrapping_add(t))];
+    self.z = s[usize::from(self.j.wrapping_add(t))];
+    self.z
+  }
+}
+
+impl Default for Spritz {
+  fn default() -> Spritz {
+    Spritz::new()
+  }
+}
+
+impl Drop for Spritz {
+  fn drop(&mut self) {
+    wipe::wipe(&mut self.s);
+
+    for x in [&mut self.i, &mut self.j, &mut self.k, &mut self.z, &mut self.a, &mut self.w] {
+      wipe::wipe_value(x);
+    }
+  }
+}
+
+impl fmt::Debug for Spritz {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Spritz").finish_non_exhaustive()
+  }
+}
+
+/// Hashes `msg` to a digest of `len` bytes.
+pub fn hash(msg: &[u8], len: u8) -> Vec<u8> {
+  let mut spritz = Spritz::new();
+  spritz.absorb(msg);
+  spritz.absorb_stop();
+  spritz.absorb(&[len]);
+
+  let mut out = vec![0; usize::from(len)];
+  spritz.squeeze(&mut out);
+  out
+}
+
+/// Computes a `TAG_LEN`-byte MAC over `msg` with `key`.
+pub fn mac(key: &[u8], msg: &[u8]) -> [u8; TAG_LEN] {
+  let mut spritz = Spritz::with_key(key);
+  spritz.absorb_stop();
+  spritz.absorb(msg);
+  spritz.absorb_stop();
+  spritz.absorb(&[TAG_LEN as u8]);
+
+  let mut tag = [0; TAG_LEN];
+  spritz.squeeze(&mut tag);
+  tag
+}
+
+/// Checks a MAC produced by `mac` in constant time.
+pub fn verify_mac(key: &[u8], msg: &[u8], tag: &[u8]) -> Result<()> {
+  let mut expected = mac(key, msg);
+  let ok = ct::eq(&expected, tag);
+  wipe::wipe(&mut expected);
+
+  if ok {
+    Ok(())
+  } else {
+    Err(Error::AuthenticationFailed)
+  }
+}
+
+/// Encrypts `msg` with `key`.
+pub fn encrypt(key: &[u8], msg: &[u8]) -> Vec<u8> {
+  let mut out = msg.to_vec();
+  Spritz::with_key(key).apply_keystream(&mut out);
+  out
+}
+
+/// Decrypts `ciphertext` produced by `encrypt`.
+pub fn decrypt(key: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+  encrypt(key, ciphertext)
+}
+
+/// Encrypts `msg` with `key` and `iv`.
+pub fn encrypt_with_iv(key: &[u8], iv: &[u8], msg: &[u8]) -> Vec<u8> {
+  let mut spritz = Spritz::with_key(key);
+  spritz.absorb_stop();
+  spritz.absorb(iv);
+
+  let mut out = msg.to_vec();
+  spritz.apply_keystream(&mut out);
+  out
+}
+
+/// Decrypts `ciphertext` produced by `encrypt_with_iv`.
+pub fn decrypt_with_iv(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+  encrypt_with_iv(key, iv, ciphertext)
+}
+
+/// Encrypts `msg` and authenticates it along with `ad`, returning the
+/// ciphertext followed by a `TAG_LEN`-byte tag.
+///
+/// `nonce` must never be reused with the same key.
+pub fn aead_encrypt(key: &[u8], nonce: &[u8], ad: &[u8], msg: &[u8]) -> Vec<u8> {
+  let mut spritz = aead_setup(key, nonce, ad);
+  let mut out = msg.to_vec();
+
+  for block in out.chunks_mut(AEAD_BLOCK) {
+    spritz.apply_keystream(block);
+    spritz.absorb(block);
+  }
+
+  out.extend_from_slice(&aead_tag(spritz));
+  out
+}
+
+/// Verifies and decrypts the output of `aead_encrypt`.
+///
+/// No plaintext is returned unless the tag is valid.
+pub fn aead_decrypt(key: &[u8], nonce: &[u8], ad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+  if sealed.len() < TAG_LEN {
+    return Err(Error::AuthenticationFailed);
+  }
+
+  let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+  let mut spritz = aead_setup(key, nonce, ad);
+  let mut out = ciphertext.to_vec();
+
+  for (block, cblock) in out.chunks_mut(AEAD_BLOCK).zip(ciphertext.chunks(AEAD_BLOCK)) {
+    spritz.apply_keystream(block);
+    spritz.absorb(cblock);
+  }
+
+  let mut expected = aead_tag(spritz);
+  let ok = ct::eq(&expected, tag);
+  wipe::wipe(&mut expected);
+
+  if !ok {
+    wipe::wipe(&mut out);
+    return Err(Error::AuthenticationFailed);
+  }
+
+  Ok(out)
+}
+
+fn aead_setup(key: &[u8], nonce: &[u8], ad: &[u8]) -> Spritz {
+  let mut spritz = Spritz::with_key(key);
+  spritz.absorb_stop();
+  spritz.absorb(nonce);
+  spritz.absorb_stop();
+  spritz.absorb(ad);
+  spritz.absorb_stop();
+  spritz
+}
+
+fn aead_tag(mut spritz: Spritz) -> [u8; TAG_LEN] {
+  spritz.absorb_stop();
+  spritz.absorb(&[TAG_LEN as u8]);
+
+  let mut tag = [0; TAG_LEN];
+  spritz.squeeze(&mut tag);
+  tag
+}
+
+#[cfg(test)]
+mod test {
+  use crate::spritz::{self, Spritz};
+  use crate::test_util::from_hex;
+  use crate::Error;
+
+  #[test]
+  fn test_output() {
+    // Appendix E of the paper: the first bytes output after absorbing
+    // each input.
+    for &(input, hex) in &[
+      ("ABC", "779a8e01f9e9cbc0"),
+      ("spam", "f0609a1df143cebf"),
+      ("arcfour", "1afa8b5ee337dbc7")
+    ] {
+      let mut out = [0u8; 8];
+      Spritz::with_key(input.as_bytes()).squeeze(&mut out);
+      assert_eq!(out[..], from_hex(hex)[..], "input {}", input);
+    }
+  }
+
+  #[test]
+  fn test_hash() {
+    // Appendix E: the first bytes of each 32-byte digest.
+    for &(input, hex) in &[
+      ("ABC", "028fa2b48b934a18"),
+      ("spam", "acbba0813f300d3a"),
+      ("arcfour", "ff8cf268094c87b9")
+    ] {
+      let digest = spritz::hash(input.as_bytes(), 32);
+      assert_eq!(digest.len(), 32);
+      assert_eq!(digest[..8], from_hex(hex)[..], "input {}", input);
+    }
+  }
+
+  #[test]
+  fn test_encrypt() {
+    let msg = b"Attack at dawn";
+    let ciphertext = spritz::encrypt(b"Secret", msg);
+    assert_ne!(&ciphertext[..], &msg[..]);
+    assert_eq!(spritz::decrypt(b"Secret", &ciphertext), msg);
+
+    let ciphertext = spritz::encrypt_with_iv(b"Secret", b"iv", msg);
+    assert_ne!(ciphertext, spritz::encrypt_with_iv(b"Secret", b"iw", msg));
+    assert_eq!(spritz::decrypt_with_iv(b"Secret", b"iv", &ciphertext), msg);
+  }
+
+  #[test]
+  fn test_mac() {
+    let tag = spritz::mac(b"key", b"message");
+    assert!(spritz::verify_mac(b"key", b"message", &tag).is_ok());
+    assert!(spritz::verify_mac(b"key", b"messagf", &tag).is_err());
+    assert!(spritz::verify_mac(b"kez", b"message", &tag).is_err());
+    assert!(spritz::verify_mac(b"key", b"message", &tag[..16]).is_err());
+  }
+
+  #[test]
+  fn test_aead() {
+    // Longer than one absorb block, and not a multiple of it.
+    let msg: Vec<u8> = (0..200).map(|b| b as u8).collect();
+    let sealed = spritz::aead_encrypt(b"key", b"nonce", b"header", &msg);
+    assert_eq!(sealed.len(), msg.len() + spritz::TAG_LEN);
+
+    let opened = spritz::aead_decrypt(b"key", b"nonce", b"header", &sealed).unwrap();
+    assert_eq!(opened, msg);
+
+    let mut tampered = sealed.clone();
+    tampered[100] ^= 1;
+    assert!(matches!(
+      spritz::aead_decrypt(b"key", b"nonce", b"header", &tampered),
+      Err(Error::AuthenticationFailed)
+    ));
+    assert!(spritz::aead_decrypt(b"key", b"nonce", b"footer", &sealed).is_err());
+    assert!(spritz::aead_decrypt(b"key", b"nonse", b"header", &sealed).is_err());
+    assert!(spritz::aead_decrypt(b"key", b"nonce", b"header", &sealed[..10]).is_err());
+  }
+}
@@ -0,0 +1,338 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The SSH `arcfour`, `arcfour128` and `arcfour256` ciphers.
+//!
+//! `arcfour` (RFC 4253) keys RC4 with 128 bits and uses the keystream
+//! from the start. `arcfour128` and `arcfour256` (RFC 4345) use 128 and
+//! 256 bits and discard the first 1536 bytes.
+//!
+//! Each direction of a connection has its own `Direction`, whose
+//! keystream runs on across packets. MACs are negotiated separately in
+//! SSH; `Packet::as_bytes` returns the unencrypted packet they cover
+//! and `Direction::sequence_number` the number they include.
+
+use std::fmt;
+
+use crate::{Error, Rc4, Result};
+
+/// The cipher block size used for padding; stream ciphers use 8.
+pub const BLOCK_SIZE: usize = 8;
+
+/// The smallest amount of padding a packet may have.
+pub const MIN_PADDING: usize = 4;
+
+/// The largest packet accepted, like OpenSSH.
+pub const MAX_PACKET_LEN: usize = 256 * 1024;
+
+/// The keystream bytes `arcfour128` and `arcfour256` discard.
+pub const DISCARD: usize = 1536;
+
+/// An SSH arcfour cipher.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+  Arcfour,
+  Arcfour128,
+  Arcfour256
+}
+
+impl Algorithm {
+  /// Looks up a cipher by its SSH name.
+  pub fn from_name(name: &str) -> Option<Algorithm> {
+    match name {
+      "arcfour" => Some(Algorithm::Arcfour),
+      "arcfour128" => Some(Algorithm::Arcfour128),
+      "arcfour256" => Some(Algorithm::Arcfour256),
+      _ => None
+    }
+  }
+
+  /// Returns the SSH name.
+  pub fn name(self) -> &'static str {
+    match self {
+      Algorithm::Arcfour => "arcfour",
+      Algorithm::Arcfour128 => "arcfour128",
+      Algorithm::Arcfour256 => "arcfour256"
+    }
+  }
+
+  /// Returns the key length in bytes.
+  pub fn key_len(self) -> usize {
+    match self {
+      Algorithm::Arcfour | Algorithm::Arcfour128 => 16,
+      Algorithm::Arcfour256 => 32
+    }
+  }
+
+  /// Returns the number of keystream bytes discarded after keying.
+  pub fn discard(self) -> usize {
+    match self {
+      Algorithm::Arcfour => 0,
+      Algorithm::Arcfour128 | Algorithm::Arcfour256 => DISCARD
+    }
+  }
+}
+
+/// A decrypted binary packet.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Packet {
+  bytes: Vec<u8>
+}
+
+impl Packet {
+  /// Returns the whole unencrypted packet: length, padding length,
+  /// payload and padding.
+  pub fn as_bytes(&self) -> &[u8] {
+    &self.bytes
+  }
+
+  /// Returns the payload.
+  pub fn payload(&self) -> &[u8] {
+    let padding = usize::from(self.bytes[4]);
+    &self.bytes[5..self.bytes.len() - padding]
+  }
+
+  /// Returns the packet's length on the wire, not counting any MAC.
+  pub fn len(&self) -> usize {
+    self.bytes.len()
+  }
+
+  /// Returns true if the packet has no bytes, which never happens.
+  pub fn is_empty(&self) -> bool {
+    self.bytes.is_empty()
+  }
+}
+
+impl fmt::Debug for Packet {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Packet").field("len", &self.bytes.len()).finish_non_exhaustive()
+  }
+}
+
+/// The cipher state for one direction of an SSH connection.
+///
+/// `Direction` isn't `Clone`, since two copies would encrypt different
+/// packets with the same keystream.
+pub struct Direction {
+  algorithm: Algorithm,
+  rc4: Rc4,
+  seq: u32
+}
+
+impl Direction {
+  /// Keys the cipher with the first `algorithm.key_len()` bytes of
+  /// `key`, which is how SSH takes keys from its key derivation.
+  pub fn new(algorithm: Algorithm, key: &[u8]) -> Result<Direction> {
+    let len = algorithm.key_len();
+    if key.len() < len {
+      return Err(Error::InvalidParameter("SSH arcfour key is too short"));
+    }
+
+    let rc4 = Rc4::try_new_drop(&key[..len], algorithm.discard())?;
+    Ok(Direction { algorithm, rc4, seq: 0 })
+  }
+
+  /// Returns the cipher.
+  pub fn algorithm(&self) -> Algorithm {
+    self.algorithm
+  }
+
+  /// Returns the sequence number of the next packet.
+  pub fn sequence_number(&self) -> u32 {
+    self.seq
+  }
+
+  /// Builds a packet around `payload` with random padding and
+  /// encrypts it.
+  #[cfg(feature = "getrandom")]
+  pub fn encrypt_packet(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+    self.seal(payload, crate::key::random)
+  }
+
+  /// Decrypts the packet at the start of `data`.
+  ///
+  /// Returns `None`, without touching the keystream, if `data` doesn't
+  /// hold the whole packet yet. Anything after the packet, such as its
+  /// MAC, is left for the caller.
+  pub fn decrypt_packet(&mut self, data: &[u8]) -> Result<Option<Packet>> {
+    if data.len() < BLOCK_SIZE {
+      return Ok(None);
+    }
+
+    // Peek at the length with a copy of the keystream.
+    let mut head = [0u8; 4];
+    head.copy_from_slice(&data[..4]);
+    self.rc4.clone().try_apply_keystream(&mut head)?;
+
+    let len = u32::from_be_bytes(head) as usize + 4;
+    if len > MAX_PACKET_LEN || !len.is_multiple_of(BLOCK_SIZE) || len < 2 * BLOCK_SIZE {
+      return Err(Error::Format("bad SSH packet length"));
+    }
+
+    if data.len() < len {
+      return Ok(None);
+    }
+
+    let mut bytes = data[..len].to_vec();
+    self.rc4.try_apply_keystream(&mut bytes)?;
+    self.seq = self.seq.wrapping_add(1);
+
+    let padding = usize::from(bytes[4]);
+    if padding < MIN_PADDING || padding > len - 5 {
+      return Err(Error::Format("bad SSH padding length"));
+    }
+
+    Ok(Some(Packet { bytes }))
+  }
+
+  /// Applies the keystream to `data` in place, for callers that frame
+  /// packets themselves.
+  pub fn apply_keystream(&mut self, data: &mut [u8]) -> Result<()> {
+    self.rc4.try_apply_keystream(data)
+  }
+
+  #[cfg(any(test, feature = "getrandom"))]
+  fn seal(&mut self, payload: &[u8], fill: impl Fn(&mut [u8]) -> Result<()>) -> Result<Vec<u8>> {
+    let mut padding = BLOCK_SIZE - (5 + payload.len()) % BLOCK_SIZE;
+    if padding < MIN_PADDING {
+      padding += BLOCK_SIZE;
+    }
+
+    let len = 5 + payload.len() + padding;
+    if len > MAX_PACKET_LEN {
+      return Err(Error::InvalidParameter("SSH payload is too large"));
+    }
+
+    let mut packet = Vec::with_capacity(len);
+    packet.extend_from_slice(&((len - 4) as u32).to_be_bytes());
+    packet.push(padding as u8);
+    packet.extend_from_slice(payload);
+    packet.resize(len, 0);
+    fill(&mut packet[len - padding..])?;
+
+    self.rc4.try_apply_keystream(&mut packet)?;
+    self.seq = self.seq.wrapping_add(1);
+    Ok(packet)
+  }
+}
+
+impl fmt::Debug for Direction {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Direction").field("algorithm", &self.algorithm).finish_non_exhaustive()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::ssh::{Algorithm, Direction};
+  use crate::test_util::{from_hex, to_hex};
+  use crate::Error;
+
+  // OpenSSH no longer offers arcfour, so these come from Python's
+  // `cryptography` ARC4 following RFC 4253 and RFC 4345. Each is two
+  // consecutive packets with zero padding, keyed with 00 01 02 ...
+  const VECTORS: &[(Algorithm, &str)] = &[
+    (
+      Algorithm::Arcfour,
+      "e99c40ed408470be75afb7b66fbe412aa771815ff2b742ee8f9ea5fdff448661\
+       34542f33106f8d76e6324ffbc22c9a01e2e173fe54489ea686f807a309c72b66"
+    ),
+    (
+      Algorithm::Arcfour128,
+      "b3e5acf672e61f9890c8924e383ed2b7269d41a222f6bfb02051270d1addd051\
+       048aa8be11a0724898839cf05448926d490382961a3795afa45e99884412130c"
+    ),
+    (
+      Algorithm::Arcfour256,
+      "c63ff86aea4bad9128eda6c6433113466f02decff9d15532207409182af7cc7f\
+       83f26822671501447202afc83976e211e3d1d41f7329662e9e63a1160047c507"
+    )
+  ];
+
+  const FIRST: &[u8] = b"first packet";
+  const SECOND: &[u8] = b"second, longer packet payload";
+
+  fn key(algorithm: Algorithm) -> Vec<u8> {
+    (0..algorithm.key_len() as u8).collect()
+  }
+
+  fn zeros(buf: &mut [u8]) -> crate::Result<()> {
+    buf.fill(0);
+    Ok(())
+  }
+
+  #[test]
+  fn test_vectors() {
+    for &(algorithm, hex) in VECTORS {
+      let mut tx = Direction::new(algorithm, &key(algorithm)).unwrap();
+      let mut wire = tx.seal(FIRST, zeros).unwrap();
+      wire.extend(tx.seal(SECOND, zeros).unwrap());
+      assert_eq!(to_hex(&wire), hex, "{}", algorithm.name());
+      assert_eq!(tx.sequence_number(), 2);
+
+      let mut rx = Direction::new(algorithm, &key(algorithm)).unwrap();
+      let data = from_hex(hex);
+      let first = rx.decrypt_packet(&data).unwrap().unwrap();
+      assert_eq!(first.payload(), FIRST);
+      assert_eq!(first.len(), 24);
+
+      let second = rx.decrypt_packet(&data[first.len()..]).unwrap().unwrap();
+      assert_eq!(second.payload(), SECOND);
+      assert_eq!(&second.as_bytes()[..5], &[0, 0, 0, 36, 6]);
+    }
+  }
+
+  #[test]
+  #[cfg(feature = "getrandom")]
+  fn test_roundtrip() {
+    for name in ["arcfour", "arcfour128", "arcfour256"] {
+      let algorithm = Algorithm::from_name(name).unwrap();
+      assert_eq!(algorithm.name(), name);
+
+      // Longer keys are truncated like SSH key derivation output.
+      let key = [0x42u8; 64];
+      let mut tx = Direction::new(algorithm, &key).unwrap();
+      let mut rx = Direction::new(algorithm, &key[..algorithm.key_len()]).unwrap();
+
+      for len in [0, 1, 3, 4, 100, 1000] {
+        let payload = vec![0xa5; len];
+        let wire = tx.encrypt_packet(&payload).unwrap();
+        assert!(wire.len().is_multiple_of(8));
+
+        let packet = rx.decrypt_packet(&wire).unwrap().unwrap();
+        assert_eq!(packet.payload(), &payload[..]);
+        assert_eq!(rx.sequence_number(), tx.sequence_number());
+      }
+    }
+
+    assert_eq!(Algorithm::from_name("aes128-ctr"), None);
+  }
+
+  #[test]
+  fn test_partial_packet() {
+    let algorithm = Algorithm::Arcfour128;
+    let mut tx = Direction::new(algorithm, &key(algorithm)).unwrap();
+    let mut rx = Direction::new(algorithm, &key(algorithm)).unwrap();
+    let wire = tx.seal(b"payload", zeros).unwrap();
+
+    // Incomplete packets leave the keystream where it was.
+    assert!(rx.decrypt_packet(&wire[..7]).unwrap().is_none());
+    assert!(rx.decrypt_packet(&wire[..wire.len() - 1]).unwrap().is_none());
+    assert_eq!(rx.sequence_number(), 0);
+    assert_eq!(rx.decrypt_packet(&wire).unwrap().unwrap().payload(), b"payload");
+  }
+
+  #[test]
+  fn test_errors() {
+    assert!(Direction::new(Algorithm::Arcfour256, &[0; 16]).is_err());
+
+    let algorithm = Algorithm::Arcfour;
+    let mut rx = Direction::new(algorithm, &key(algorithm)).unwrap();
+    let result = rx.decrypt_packet(&[0xff; 64]);
+    assert!(matches!(result, Err(Error::Format(_))));
+
+    let direction = Direction::new(algorithm, &key(algorithm)).unwrap();
+    assert_eq!(format!("{:?}", direction), "Direction { algorithm: Arcfour, .. }");
+  }
+}
@@ -0,0 +1,110 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Implementations of the RustCrypto `cipher` traits.
+
+use cipher::consts::U16;
+use cipher::inout::InOutBuf;
+use cipher::{
+  InvalidLength, Key, KeyInit, KeySizeUser, OverflowError, SeekNum, StreamCipher,
+  StreamCipherError, StreamCipherSeek
+};
+
+use crate::Rc4;
+
+impl KeySizeUser for Rc4 {
+  /// The nominal key size is 128 bits, but `new_from_slice` accepts
+  /// any key of 1 to 256 bytes.
+  type KeySize = U16;
+}
+
+impl KeyInit for Rc4 {
+  fn new(key: &Key<Rc4>) -> Rc4 {
+    Rc4::new(key)
+  }
+
+  fn new_from_slice(key: &[u8]) -> Result<Rc4, InvalidLength> {
+    Rc4::try_new(key).map_err(|_| InvalidLength)
+  }
+}
+
+impl StreamCipher for Rc4 {
+  fn try_apply_keystream_inout(
+    &mut self,
+    mut buf: InOutBuf<'_, '_, u8>
+  ) -> Result<(), StreamCipherError> {
+    let mut ks = [0u8; 256];
+
+    while !buf.is_empty() {
+      let len = buf.len().min(ks.len());
+      let (mut head, tail) = buf.split_at(len);
+
+      ks[..len].fill(0);
+      self.try_apply_keystream(&mut ks[..len]).map_err(|_| StreamCipherError)?;
+      head.xor_in2out(&ks[..len]);
+      buf = tail;
+    }
+
+    crate::wipe::wipe(&mut ks);
+    Ok(())
+  }
+}
+
+/// RC4 can only move forwards: seeking to a position before the
+/// current one fails with `StreamCipherError`.
+impl StreamCipherSeek for Rc4 {
+  fn try_current_pos<T: SeekNum>(&self) -> Result<T, OverflowError> {
+    T::from_block_byte(self.position(), 0, 1)
+  }
+
+  fn try_seek<T: SeekNum>(&mut self, pos: T) -> Result<(), StreamCipherError> {
+    let (pos, _) = pos.into_block_byte::<u64>(1).map_err(|_| StreamCipherError)?;
+
+    if pos < self.position() {
+      return Err(StreamCipherError);
+    }
+
+    self.skip(pos - self.position()).map_err(|_| StreamCipherError)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::Rc4;
+  use cipher::{KeyInit, StreamCipher, StreamCipherSeek};
+
+  fn generic_encrypt<C: KeyInit + StreamCipher>(key: &[u8], buf: &mut [u8]) {
+    let mut c = C::new_from_slice(key).unwrap();
+    c.apply_keystream(buf);
+  }
+
+  #[test]
+  fn test_key_init() {
+    let mut buf = *b"Plaintext";
+    generic_encrypt::<Rc4>(b"Key", &mut buf);
+    assert_eq!(buf, [0xBB, 0xF3, 0x16, 0xE8, 0xD9, 0x40, 0xAF, 0x0A, 0xD3]);
+
+    assert!(Rc4::new_from_slice(b"").is_err());
+    assert!(Rc4::new_from_slice(&[0; 257]).is_err());
+    assert!(Rc4::new_from_slice(&[0; 256]).is_ok());
+  }
+
+  #[test]
+  fn test_seek() {
+    let key = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+    let mut rc4 = <Rc4 as KeyInit>::new(&key.into());
+    assert_eq!(rc4.current_pos::<u32>(), 0);
+
+    rc4.seek(768u32);
+    assert_eq!(rc4.current_pos::<u64>(), 768);
+
+    let mut buf = [0u8; 4];
+    StreamCipher::apply_keystream(&mut rc4, &mut buf);
+    assert_eq!(buf, [0xEC, 0xCB, 0xE1, 0x3D]);
+    assert_eq!(rc4.current_pos::<u64>(), 772);
+
+    assert!(rc4.try_seek(16u32).is_err());
+    assert!(rc4.try_seek(772u32).is_ok());
+  }
+}
@@ -0,0 +1,18 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Helpers shared by the test modules.
+
+pub fn from_hex(hex: &str) -> Vec<u8> {
+  let hex: Vec<u8> = hex.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+
+  hex
+    .chunks(2)
+    .map(|c| u8::from_str_radix(std::str::from_utf8(c).unwrap(), 16).unwrap())
+    .collect()
+}
+
+pub fn to_hex(buf: &[u8]) -> String {
+  buf.iter().map(|b| format!("{:02x}", b)).collect()
+}
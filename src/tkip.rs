@@ -0,0 +1,626 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! TKIP, the WPA frame protection retrofitted onto WEP hardware.
+//!
+//! Every frame gets its own 128-bit RC4 key, mixed in two phases from
+//! the temporal key, the transmitter address and the 48-bit TKIP
+//! sequence counter (TSC). The MSDU is protected by the Michael MIC
+//! before the CRC-32 ICV is added, and receivers reject frames whose
+//! TSC doesn't increase.
+//!
+//! MSDUs are encrypted into a single MPDU; fragmentation is left to the
+//! caller. TKIP is deprecated and only here for legacy equipment.
+
+use std::fmt;
+
+use crate::{ct, wipe, Error, Rc4, Result};
+
+/// The length of the temporal encryption key.
+pub const TK_LEN: usize = 16;
+
+/// The length of a Michael key and of the MIC it produces.
+pub const MIC_LEN: usize = 8;
+
+/// The length of the IV and extended IV in front of the ciphertext.
+pub const HEADER_LEN: usize = 8;
+
+/// The length of the encrypted CRC-32 after the MIC.
+pub const ICV_LEN: usize = 4;
+
+/// The largest 48-bit sequence counter.
+pub const MAX_TSC: u64 = (1 << 48) - 1;
+
+/// The number of traffic classes with their own replay counter.
+pub const PRIORITIES: usize = 16;
+
+const EXT_IV: u8 = 0x20;
+
+const AES_SBOX: [u8; 256] = [
+  0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+  0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+  0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+  0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+  0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+  0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+  0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+  0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+  0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+  0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+  0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+  0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+  0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+  0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+  0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+  0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16
+];
+
+// The TKIP S-box: 2·S[x] in the high byte and 3·S[x] in the low byte,
+// multiplied in GF(2^8) like an AES MixColumns row.
+const TKIP_SBOX: [u16; 256] = tkip_sbox();
+
+const fn tkip_sbox() -> [u16; 256] {
+  let mut table = [0u16; 256];
+  let mut i = 0;
+
+  while i < 256 {
+    let s = AES_SBOX[i];
+    let s2 = (s << 1) ^ if s & 0x80 != 0 { 0x1b } else { 0 };
+    table[i] = ((s2 as u16) << 8) | (s2 ^ s) as u16;
+    i += 1;
+  }
+
+  table
+}
+
+fn sbox(v: u16) -> u16 {
+  TKIP_SBOX[usize::from(v as u8)] ^ TKIP_SBOX[usize::from(v >> 8)].swap_bytes()
+}
+
+fn tk16(tk: &[u8; TK_LEN], n: usize) -> u16 {
+  u16::from_le_bytes([tk[n], tk[n + 1]])
+}
+
+/// Phase 1 key mixing of the temporal key, the transmitter address and
+/// the upper 32 bits of the TSC. The result only changes every 65536
+/// frames, so callers can cache it.
+pub fn phase1(tk: &[u8; TK_LEN], ta: &[u8; 6], iv32: u32) -> [u16; 5] {
+  let mut p1k = [
+    iv32 as u16,
+    (iv32 >> 16) as u16,
+    u16::from_le_bytes([ta[0], ta[1]]),
+    u16::from_le_bytes([ta[2], ta[3]]),
+    u16::from_le_bytes([ta[4], ta[5]])
+  ];
+
+  for i in 0..8 {
+    let j = 2 * (i & 1);
+    p1k[0] = p1k[0].wrapping_add(sbox(p1k[4] ^ tk16(tk, j)));
+    p1k[1] = p1k[1].wrapping_add(sbox(p1k[0] ^ tk16(tk, 4 + j)));
+    p1k[2] = p1k[2].wrapping_add(sbox(p1k[1] ^ tk16(tk, 8 + j)));
+    p1k[3] = p1k[3].wrapping_add(sbox(p1k[2] ^ tk16(tk, 12 + j)));
+    p1k[4] = p1k[4].wrapping_add(sbox(p1k[3] ^ tk16(tk, j))).wrapping_add(i as u16);
+  }
+
+  p1k
+}
+
+/// Phase 2 key mixing of the phase 1 output and the lower 16 bits of
+/// the TSC into the per-frame RC4 key.
+pub fn phase2(tk: &[u8; TK_LEN], p1k: &[u16; 5], iv16: u16) -> [u8; 16] {
+  let mut ppk = [p1k[0], p1k[1], p1k[2], p1k[3], p1k[4], p1k[4].wrapping_add(iv16)];
+
+  for k in 0..6 {
+    ppk[k] = ppk[k].wrapping_add(sbox(ppk[(k + 5) % 6] ^ tk16(tk, 2 * k)));
+  }
+
+  ppk[0] = ppk[0].wrapping_add((ppk[5] ^ tk16(tk, 12)).rotate_right(1));
+  ppk[1] = ppk[1].wrapping_add((ppk[0] ^ tk16(tk, 14)).rotate_right(1));
+  for k in 2..6 {
+    ppk[k] = ppk[k].wrapping_add(ppk[k - 1].rotate_right(1));
+  }
+
+  // The first three bytes are the WEP IV, with a middle byte chosen to
+  // avoid the weak keys of the FMS attack.
+  let [iv0, iv2] = iv16.to_be_bytes();
+  let mut key = [0u8; 16];
+  key[0] = iv0;
+  key[1] = (iv0 | EXT_IV) & 0x7f;
+  key[2] = iv2;
+  key[3] = ((ppk[5] ^ tk16(tk, 0)) >> 1) as u8;
+
+  for (k, v) in ppk.iter().enumerate() {
+    key[4 + 2 * k..6 + 2 * k].copy_from_slice(&v.to_le_bytes());
+  }
+
+  wipe::wipe_value(&mut ppk);
+  key
+}
+
+/// Computes the Michael MIC of `data`.
+pub fn michael(key: &[u8; MIC_LEN], data: &[u8]) -> [u8; MIC_LEN] {
+  let mut m = Michael::new(key);
+  m.update(data);
+  m.finish()
+}
+
+struct Michael {
+  l: u32,
+  r: u32,
+  buf: [u8; 4],
+  len: usize
+}
+
+impl Michael {
+  fn new(key: &[u8; MIC_LEN]) -> Michael {
+    let l = u32::from_le_bytes([key[0], key[1], key[2], key[3]]);
+    let r = u32::from_le_bytes([key[4], key[5], key[6], key[7]]);
+    Michael { l, r, buf: [0; 4], len: 0 }
+  }
+
+  fn update(&mut self, data: &[u8]) {
+    for &b in data {
+      self.buf[self.len] = b;
+      self.len += 1;
+
+      if self.len == 4 {
+        self.block(u32::from_le_bytes(self.buf));
+        self.len = 0;
+      }
+    }
+  }
+
+  // Pads with 0x5a and four to seven zero bytes.
+  fn finish(mut self) -> [u8; MIC_LEN] {
+    self.update(&[0x5a, 0, 0, 0, 0]);
+    while self.len != 0 {
+      self.update(&[0]);
+    }
+
+    let mut mic = [0u8; MIC_LEN];
+    mic[..4].copy_from_slice(&self.l.to_le_bytes());
+    mic[4..].copy_from_slice(&self.r.to_le_bytes());
+    mic
+  }
+
+  fn block(&mut self, m: u32) {
+    let (mut l, mut r) = (self.l ^ m, self.r);
+
+    r ^= l.rotate_left(17);
+    l = l.wrapping_add(r);
+    r ^= ((l & 0xff00ff00) >> 8) | ((l & 0x00ff00ff) << 8);
+    l = l.wrapping_add(r);
+    r ^= l.rotate_left(3);
+    l = l.wrapping_add(r);
+    r ^= l.rotate_right(2);
+    l = l.wrapping_add(r);
+
+    self.l = l;
+    self.r = r;
+  }
+}
+
+impl Drop for Michael {
+  fn drop(&mut self) {
+    wipe::wipe_value(&mut self.l);
+    wipe::wipe_value(&mut self.r);
+    wipe::wipe(&mut self.buf);
+  }
+}
+
+// The MIC covers DA, SA and the priority as well as the MSDU.
+fn msdu_mic(
+  key: &[u8; MIC_LEN],
+  da: &[u8; 6],
+  sa: &[u8; 6],
+  priority: u8,
+  msdu: &[u8]
+) -> [u8; MIC_LEN] {
+  let mut m = Michael::new(key);
+  m.update(da);
+  m.update(sa);
+  m.update(&[priority, 0, 0, 0]);
+  m.update(msdu);
+  m.finish()
+}
+
+// Runs both key mixing phases, caching phase 1 while IV32 is unchanged.
+struct Mixer {
+  tk: [u8; TK_LEN],
+  ta: [u8; 6],
+  p1k: Option<(u32, [u16; 5])>
+}
+
+impl Mixer {
+  fn rc4(&mut self, tsc: u64) -> Rc4 {
+    let iv32 = (tsc >> 16) as u32;
+    let p1k = match self.p1k {
+      Some((cached, p1k)) if cached == iv32 => p1k,
+      _ => phase1(&self.tk, &self.ta, iv32)
+    };
+    self.p1k = Some((iv32, p1k));
+
+    let mut key = phase2(&self.tk, &p1k, tsc as u16);
+    let rc4 = Rc4::new(&key);
+    wipe::wipe(&mut key);
+    rc4
+  }
+}
+
+impl Drop for Mixer {
+  fn drop(&mut self) {
+    wipe::wipe(&mut self.tk);
+    wipe::wipe_value(&mut self.p1k);
+  }
+}
+
+/// Encrypts MSDUs sent by one transmitter under one temporal key.
+///
+/// The key material is zeroized on drop and never shown by `Debug`.
+/// `Sender` isn't `Clone`, since two copies would reuse TSCs and with
+/// them the keystream.
+pub struct Sender {
+  mixer: Mixer,
+  mic_key: [u8; MIC_LEN],
+  key_id: u8,
+  tsc: u64
+}
+
+impl Sender {
+  /// Uses the temporal key and Tx MIC key for frames sent from `ta`,
+  /// tagged with key ID `key_id`. The TSC starts at 1.
+  pub fn new(
+    tk: &[u8; TK_LEN],
+    mic_key: &[u8; MIC_LEN],
+    ta: [u8; 6],
+    key_id: u8
+  ) -> Result<Sender> {
+    if key_id > 3 {
+      return Err(Error::InvalidParameter("TKIP key IDs range from 0 to 3"));
+    }
+
+    let mixer = Mixer { tk: *tk, ta, p1k: None };
+    Ok(Sender { mixer, mic_key: *mic_key, key_id, tsc: 1 })
+  }
+
+  /// Returns the TSC the next frame will use.
+  pub fn tsc(&self) -> u64 {
+    self.tsc
+  }
+
+  /// Encrypts an MSDU from `sa` to `da` with the given priority into
+  /// a protected frame body.
+  pub fn encrypt_msdu(
+    &mut self,
+    da: &[u8; 6],
+    sa: &[u8; 6],
+    priority: u8,
+    msdu: &[u8]
+  ) -> Result<Vec<u8>> {
+    check_priority(priority)?;
+
+    if self.tsc > MAX_TSC {
+      return Err(Error::InvalidParameter("TKIP sequence counter exhausted"));
+    }
+
+    let tsc = self.tsc;
+    self.tsc += 1;
+
+    let mic = msdu_mic(&self.mic_key, da, sa, priority, msdu);
+    Ok(seal_mpdu(&mut self.mixer, self.key_id, tsc, msdu, &mic))
+  }
+}
+
+impl Drop for Sender {
+  fn drop(&mut self) {
+    wipe::wipe(&mut self.mic_key);
+  }
+}
+
+impl fmt::Debug for Sender {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Sender").finish_non_exhaustive()
+  }
+}
+
+/// Decrypts and verifies MSDUs from one transmitter under one temporal
+/// key, keeping a replay counter per priority.
+///
+/// The key material is zeroized on drop and never shown by `Debug`.
+/// `Receiver` isn't `Clone`, since each copy would keep its own replay
+/// counters and accept the same MSDU again.
+pub struct Receiver {
+  mixer: Mixer,
+  mic_key: [u8; MIC_LEN],
+  replay: [Option<u64>; PRIORITIES]
+}
+
+impl Receiver {
+  /// Uses the temporal key and Rx MIC key for frames sent from `ta`.
+  pub fn new(tk: &[u8; TK_LEN], mic_key: &[u8; MIC_LEN], ta: [u8; 6]) -> Receiver {
+    let mixer = Mixer { tk: *tk, ta, p1k: None };
+    Receiver { mixer, mic_key: *mic_key, replay: [None; PRIORITIES] }
+  }
+
+  /// Decrypts a protected frame body carrying an MSDU from `sa` to `da`
+  /// with the given priority.
+  ///
+  /// Fails with `Error::Replay` if the TSC isn't higher than the last
+  /// one accepted for `priority`, and with `Error::AuthenticationFailed`
+  /// if the ICV or the MIC doesn't match. The replay counter only
+  /// advances once the MIC has been verified.
+  pub fn decrypt_msdu(
+    &mut self,
+    da: &[u8; 6],
+    sa: &[u8; 6],
+    priority: u8,
+    body: &[u8]
+  ) -> Result<Vec<u8>> {
+    check_priority(priority)?;
+
+    if body.len() < HEADER_LEN + MIC_LEN + ICV_LEN {
+      return Err(Error::Format("TKIP frame body is too short"));
+    }
+
+    if body[3] & EXT_IV == 0 || body[1] != (body[0] | EXT_IV) & 0x7f {
+      return Err(Error::Format("not a TKIP frame body"));
+    }
+
+    let tsc = u64::from_be_bytes([0, 0, body[7], body[6], body[5], body[4], body[0], body[2]]);
+    let last = &mut self.replay[usize::from(priority)];
+    if last.is_some_and(|last| tsc <= last) {
+      return Err(Error::Replay);
+    }
+
+    let mut data = open_mpdu(&mut self.mixer, tsc, body)?;
+    let mic = data.split_off(data.len() - MIC_LEN);
+    if !ct::eq(&msdu_mic(&self.mic_key, da, sa, priority, &data), &mic) {
+      wipe::wipe(&mut data);
+      return Err(Error::AuthenticationFailed);
+    }
+
+    *last = Some(tsc);
+    Ok(data)
+  }
+}
+
+impl Drop for Receiver {
+  fn drop(&mut self) {
+    wipe::wipe(&mut self.mic_key);
+  }
+}
+
+impl fmt::Debug for Receiver {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Receiver").finish_non_exhaustive()
+  }
+}
+
+// Builds a protected frame body: the TKIP header, then the MSDU, its
+// MIC and the ICV over both, encrypted.
+fn seal_mpdu(mixer: &mut Mixer, key_id: u8, tsc: u64, msdu: &[u8], mic: &[u8]) -> Vec<u8> {
+  let [_, _, tsc5, tsc4, tsc3, tsc2, tsc1, tsc0] = tsc.to_be_bytes();
+  let mut body = Vec::with_capacity(HEADER_LEN + msdu.len() + MIC_LEN + ICV_LEN);
+  body.extend_from_slice(&[tsc1, (tsc1 | EXT_IV) & 0x7f, tsc0, key_id << 6 | EXT_IV]);
+  body.extend_from_slice(&[tsc2, tsc3, tsc4, tsc5]);
+  body.extend_from_slice(msdu);
+  body.extend_from_slice(mic);
+
+  let icv = crc32fast::hash(&body[HEADER_LEN..]);
+  body.extend_from_slice(&icv.to_le_bytes());
+
+  mixer.rc4(tsc).apply_keystream(&mut body[HEADER_LEN..]);
+  body
+}
+
+// Decrypts a protected frame body and checks its ICV, returning the
+// MSDU with its MIC still attached.
+fn open_mpdu(mixer: &mut Mixer, tsc: u64, body: &[u8]) -> Result<Vec<u8>> {
+  let mut data = body[HEADER_LEN..].to_vec();
+  mixer.rc4(tsc).apply_keystream(&mut data);
+
+  let icv = data.split_off(data.len() - ICV_LEN);
+  if crc32fast::hash(&data).to_le_bytes() != icv[..] {
+    wipe::wipe(&mut data);
+    return Err(Error::AuthenticationFailed);
+  }
+
+  Ok(data)
+}
+
+fn check_priority(priority: u8) -> Result<()> {
+  if usize::from(priority) >= PRIORITIES {
+    return Err(Error::InvalidParameter("TKIP priorities range from 0 to 15"));
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use crate::test_util::{from_hex, to_hex};
+  use crate::tkip::{self, Mixer, Receiver, Sender, HEADER_LEN, TK_LEN};
+  use crate::Error;
+
+  fn array<const N: usize>(hex: &str) -> [u8; N] {
+    from_hex(hex).try_into().unwrap()
+  }
+
+  // IEEE 802.11 TKIP mixing function test vectors.
+  const MIXING: &[(&str, &str, u64, [u16; 5], &str)] = &[
+    (
+      "000102030405060708090a0b0c0d0e0f",
+      "102233445566",
+      0x000000000000,
+      [0x3dd2, 0x016e, 0x76f4, 0x8697, 0xb2e8],
+      "00200033ea8d2f60ca6d1374234a660b"
+    ),
+    (
+      "000102030405060708090a0b0c0d0e0f",
+      "102233445566",
+      0x000000000001,
+      [0x3dd2, 0x016e, 0x76f4, 0x8697, 0xb2e8],
+      "00200190ffdc314389a9d9d074fd20aa"
+    ),
+    (
+      "63893b250840b8ae0bd0fa7e61d2783e",
+      "64f2eaeddc25",
+      0x20dcfd43ffff,
+      [0x7c67, 0x49d7, 0x9724, 0xb5e9, 0xb4f1],
+      "ff7fff93810fc6e58f5dd326251544ce"
+    ),
+    (
+      "63893b250840b8ae0bd0fa7e61d2783e",
+      "64f2eaeddc25",
+      0x20dcfd440000,
+      [0x5a5d, 0x73a8, 0xa859, 0x2ec1, 0xdc8b],
+      "002000498ca471fcfbfaa16e3610f005"
+    )
+  ];
+
+  const TK: &str = "000102030405060708090a0b0c0d0e0f";
+  const MIC_KEY: &str = "f0e1d2c3b4a59687";
+  const TA: &str = "102233445566";
+  const DA: &str = "0011223344aa";
+
+  #[test]
+  fn test_key_mixing() {
+    for &(tk, ta, tsc, p1k, key) in MIXING {
+      let tk: [u8; TK_LEN] = array(tk);
+      let p1 = tkip::phase1(&tk, &array(ta), (tsc >> 16) as u32);
+      assert_eq!(p1, p1k);
+      assert_eq!(to_hex(&tkip::phase2(&tk, &p1, tsc as u16)), key);
+    }
+  }
+
+  #[test]
+  fn test_michael() {
+    // IEEE 802.11 Michael test vectors, each keyed with the previous MIC.
+    let chain = [
+      ("", "82925c1ca1d130b8"),
+      ("M", "434721ca40639b3f"),
+      ("Mi", "e8f9becae97e5d29"),
+      ("Mic", "90038fc6cf13c1db"),
+      ("Mich", "d55e100510128986"),
+      ("Michael", "0a942b124ecaa546")
+    ];
+
+    let mut key = [0u8; 8];
+    for (msg, mic) in chain {
+      key = tkip::michael(&key, msg.as_bytes());
+      assert_eq!(to_hex(&key), mic);
+    }
+  }
+
+  #[test]
+  fn test_encrypt_msdu() {
+    // Computed with an independent Python implementation and ARC4 from
+    // the `cryptography` package.
+    let mut sender = Sender::new(&array(TK), &array(MIC_KEY), array(TA), 1).unwrap();
+    sender.tsc = 0xa0001;
+
+    let body = sender.encrypt_msdu(&array(DA), &array(TA), 3, b"TKIP test frame").unwrap();
+    assert_eq!(
+      to_hex(&body),
+      "002001600a000000c58bd339f26e80d5ba003cd589ff1fe2f40bfac5d6c0bff8e37b16"
+    );
+    assert_eq!(sender.tsc(), 0xa0002);
+
+    let mut receiver = Receiver::new(&array(TK), &array(MIC_KEY), array(TA));
+    let msdu = receiver.decrypt_msdu(&array(DA), &array(TA), 3, &body).unwrap();
+    assert_eq!(msdu, b"TKIP test frame");
+  }
+
+  #[test]
+  fn test_annex_mpdu() {
+    // The TKIP MPDU sample from the IEEE 802.11 annex: TA 02:03:04:05:06:07,
+    // TSC 1, key ID 0. The annex gives the MIC but not the MIC key.
+    let msdu = from_hex(
+      "aaaa03000000080045000054000040004001a555c0a80a02c0a80a0108003ab0\
+       00000000cd4c05000000000008090a0b0c0d0e0f101112131415161718191a1b\
+       1c1d1e1f202122232425262728292a2b2c2d2e2f3031323334353637"
+    );
+    let mic = from_hex("6881a3f3d648d03c");
+    let body = "0020012000000000\
+                c00e14fce7cfabc77547e666e57c0dac704a1e358a88c11c8e2e282e3801027a\
+                4656055ee93e9c254702e9735805ddb5769ba73f1ebb56e844ef912285d3dd6e\
+                541e823873558adba079068abd7f7f50959675acc4b4de9aa99c05f289a7c52f\
+                ee5bfc14f6f8e5f8";
+
+    let tk = array("12345678901234567890123456789012");
+    let mut mixer = Mixer { tk, ta: array("020304050607"), p1k: None };
+    assert_eq!(to_hex(&tkip::seal_mpdu(&mut mixer, 0, 1, &msdu, &mic)), body);
+
+    let data = tkip::open_mpdu(&mut mixer, 1, &from_hex(body)).unwrap();
+    assert_eq!(data, [msdu, mic].concat());
+  }
+
+  #[test]
+  fn test_replay() {
+    let (da, ta) = (array(DA), array(TA));
+    let mut sender = Sender::new(&array(TK), &array(MIC_KEY), ta, 0).unwrap();
+    let mut receiver = Receiver::new(&array(TK), &array(MIC_KEY), ta);
+
+    let first = sender.encrypt_msdu(&da, &ta, 0, b"first").unwrap();
+    let second = sender.encrypt_msdu(&da, &ta, 0, b"second").unwrap();
+    let other = sender.encrypt_msdu(&da, &ta, 5, b"other").unwrap();
+
+    assert_eq!(receiver.decrypt_msdu(&da, &ta, 0, &second).unwrap(), b"second");
+    assert!(matches!(receiver.decrypt_msdu(&da, &ta, 0, &first), Err(Error::Replay)));
+    assert!(matches!(receiver.decrypt_msdu(&da, &ta, 0, &second), Err(Error::Replay)));
+
+    // Each priority has its own counter.
+    assert_eq!(receiver.decrypt_msdu(&da, &ta, 5, &other).unwrap(), b"other");
+
+    // Frames that fail verification don't advance the counter.
+    let third = sender.encrypt_msdu(&da, &ta, 0, b"third").unwrap();
+    let mut bad = third.clone();
+    bad[10] ^= 1;
+    assert!(receiver.decrypt_msdu(&da, &ta, 0, &bad).is_err());
+    assert_eq!(receiver.decrypt_msdu(&da, &ta, 0, &third).unwrap(), b"third");
+  }
+
+  #[test]
+  fn test_authentication() {
+    let (da, ta) = (array(DA), array(TA));
+    let mut sender = Sender::new(&array(TK), &array(MIC_KEY), ta, 0).unwrap();
+    let body = sender.encrypt_msdu(&da, &ta, 0, b"payload").unwrap();
+
+    let mut receiver = Receiver::new(&array(TK), &array(MIC_KEY), ta);
+    let mut bad = body.clone();
+    bad[HEADER_LEN] ^= 1;
+    assert!(matches!(receiver.decrypt_msdu(&da, &ta, 0, &bad), Err(Error::AuthenticationFailed)));
+
+    // The ICV passes but the MIC binds the addresses and priority.
+    let result = receiver.decrypt_msdu(&ta, &da, 0, &body);
+    assert!(matches!(result, Err(Error::AuthenticationFailed)));
+    let result = receiver.decrypt_msdu(&da, &ta, 1, &body);
+    assert!(matches!(result, Err(Error::AuthenticationFailed)));
+
+    let mut other = Receiver::new(&array(TK), &[0; 8], ta);
+    let result = other.decrypt_msdu(&da, &ta, 0, &body);
+    assert!(matches!(result, Err(Error::AuthenticationFailed)));
+  }
+
+  #[test]
+  fn test_errors() {
+    let (da, ta) = (array(DA), array(TA));
+    assert!(Sender::new(&array(TK), &array(MIC_KEY), ta, 4).is_err());
+
+    let mut sender = Sender::new(&array(TK), &array(MIC_KEY), ta, 0).unwrap();
+    assert!(matches!(sender.encrypt_msdu(&da, &ta, 16, b""), Err(Error::InvalidParameter(_))));
+
+    let body = sender.encrypt_msdu(&da, &ta, 0, b"").unwrap();
+    let mut receiver = Receiver::new(&array(TK), &array(MIC_KEY), ta);
+    assert!(matches!(receiver.decrypt_msdu(&da, &ta, 0, &body[..19]), Err(Error::Format(_))));
+
+    let mut wep = body.clone();
+    wep[3] &= !0x20;
+    assert!(matches!(receiver.decrypt_msdu(&da, &ta, 0, &wep), Err(Error::Format(_))));
+
+    sender.tsc = tkip::MAX_TSC + 1;
+    assert!(matches!(sender.encrypt_msdu(&da, &ta, 0, b""), Err(Error::InvalidParameter(_))));
+    assert_eq!(format!("{:?}", sender), "Sender { .. }");
+  }
+}
@@ -0,0 +1,586 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The TLS 1.0–1.2 record layer for `TLS_RSA_WITH_RC4_128_MD5` and
+//! `TLS_RSA_WITH_RC4_128_SHA`.
+//!
+//! Each direction keeps one RC4 keystream for the whole connection and
+//! a 64-bit sequence number. A protected record is the 5-byte header
+//! followed by the fragment and its HMAC, encrypted together:
+//!
+//! ```text
+//! type | version (u16 BE) | length (u16 BE) | RC4(fragment || MAC)
+//! MAC = HMAC(mac key, seq (u64 BE) | type | version | fragment length | fragment)
+//! ```
+//!
+//! RC4 is prohibited in TLS by RFC 7465; this is for testing old peers.
+
+use std::fmt;
+
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use sha1::Sha1;
+
+use crate::{ct, wipe, Error, Rc4, Result};
+
+/// The length of a record header.
+pub const HEADER_LEN: usize = 5;
+
+/// The length of the RC4 key in both suites.
+pub const KEY_LEN: usize = 16;
+
+/// The largest plaintext fragment a record may carry.
+pub const MAX_FRAGMENT_LEN: usize = 1 << 14;
+
+/// The largest protected fragment a record may carry.
+pub const MAX_CIPHERTEXT_LEN: usize = MAX_FRAGMENT_LEN + 2048;
+
+/// An RC4 cipher suite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CipherSuite {
+  /// `TLS_RSA_WITH_RC4_128_MD5`, 0x0004.
+  Rc4128Md5,
+  /// `TLS_RSA_WITH_RC4_128_SHA`, 0x0005.
+  Rc4128Sha
+}
+
+impl CipherSuite {
+  /// Looks up a suite by its IANA number.
+  pub fn from_id(id: u16) -> Option<CipherSuite> {
+    match id {
+      0x0004 => Some(CipherSuite::Rc4128Md5),
+      0x0005 => Some(CipherSuite::Rc4128Sha),
+      _ => None
+    }
+  }
+
+  /// Returns the IANA number.
+  pub fn id(self) -> u16 {
+    match self {
+      CipherSuite::Rc4128Md5 => 0x0004,
+      CipherSuite::Rc4128Sha => 0x0005
+    }
+  }
+
+  /// Returns the length of the MAC and of its key.
+  pub fn mac_len(self) -> usize {
+    match self {
+      CipherSuite::Rc4128Md5 => 16,
+      CipherSuite::Rc4128Sha => 20
+    }
+  }
+
+  /// Returns how many bytes of the key block the suite uses.
+  pub fn key_block_len(self) -> usize {
+    2 * (self.mac_len() + KEY_LEN)
+  }
+}
+
+/// A protocol version that may use the RC4 suites.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+  Tls10,
+  Tls11,
+  Tls12
+}
+
+impl Version {
+  /// Parses the version from its wire encoding.
+  pub fn from_u16(version: u16) -> Option<Version> {
+    match version {
+      0x0301 => Some(Version::Tls10),
+      0x0302 => Some(Version::Tls11),
+      0x0303 => Some(Version::Tls12),
+      _ => None
+    }
+  }
+
+  /// Returns the wire encoding.
+  pub fn to_u16(self) -> u16 {
+    match self {
+      Version::Tls10 => 0x0301,
+      Version::Tls11 => 0x0302,
+      Version::Tls12 => 0x0303
+    }
+  }
+}
+
+/// The type of a record's contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentType {
+  ChangeCipherSpec,
+  Alert,
+  Handshake,
+  ApplicationData
+}
+
+impl ContentType {
+  /// Parses the type from its wire encoding.
+  pub fn from_u8(content_type: u8) -> Option<ContentType> {
+    match content_type {
+      20 => Some(ContentType::ChangeCipherSpec),
+      21 => Some(ContentType::Alert),
+      22 => Some(ContentType::Handshake),
+      23 => Some(ContentType::ApplicationData),
+      _ => None
+    }
+  }
+
+  /// Returns the wire encoding.
+  pub fn to_u8(self) -> u8 {
+    match self {
+      ContentType::ChangeCipherSpec => 20,
+      ContentType::Alert => 21,
+      ContentType::Handshake => 22,
+      ContentType::ApplicationData => 23
+    }
+  }
+}
+
+/// Which end of the connection keys are taken for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+  Client,
+  Server
+}
+
+/// An unprotected record.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Record {
+  content_type: ContentType,
+  fragment: Vec<u8>,
+  len: usize
+}
+
+impl Record {
+  /// Returns the content type.
+  pub fn content_type(&self) -> ContentType {
+    self.content_type
+  }
+
+  /// Returns the plaintext fragment.
+  pub fn fragment(&self) -> &[u8] {
+    &self.fragment
+  }
+
+  /// Returns the plaintext fragment, consuming the record.
+  pub fn into_fragment(self) -> Vec<u8> {
+    self.fragment
+  }
+
+  /// Returns the length of the protected record on the wire.
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  /// Returns true if the record has no bytes, which never happens.
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+}
+
+impl fmt::Debug for Record {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Record")
+      .field("content_type", &self.content_type)
+      .field("len", &self.len)
+      .finish_non_exhaustive()
+  }
+}
+
+#[derive(Clone)]
+enum RecordMac {
+  Md5(Hmac<Md5>),
+  Sha(Hmac<Sha1>)
+}
+
+impl RecordMac {
+  fn new(suite: CipherSuite, key: &[u8]) -> RecordMac {
+    let error = "HMAC accepts any key length";
+    match suite {
+      CipherSuite::Rc4128Md5 => RecordMac::Md5(Mac::new_from_slice(key).expect(error)),
+      CipherSuite::Rc4128Sha => RecordMac::Sha(Mac::new_from_slice(key).expect(error))
+    }
+  }
+
+  fn compute(&self, header: &[u8], fragment: &[u8]) -> Vec<u8> {
+    match self.clone() {
+      RecordMac::Md5(mut mac) => {
+        mac.update(header);
+        mac.update(fragment);
+        mac.finalize().into_bytes().to_vec()
+      }
+      RecordMac::Sha(mut mac) => {
+        mac.update(header);
+        mac.update(fragment);
+        mac.finalize().into_bytes().to_vec()
+      }
+    }
+  }
+}
+
+/// The record protection state for one direction of a connection.
+///
+/// `Direction` isn't `Clone`, since two copies would protect different
+/// records with the same keystream and sequence numbers.
+pub struct Direction {
+  suite: CipherSuite,
+  version: Version,
+  mac: RecordMac,
+  rc4: Rc4,
+  seq: u64
+}
+
+impl Direction {
+  /// Sets up a direction from its MAC key and RC4 key, with the
+  /// sequence number at zero as after a ChangeCipherSpec.
+  pub fn new(
+    suite: CipherSuite,
+    version: Version,
+    mac_key: &[u8],
+    key: &[u8]
+  ) -> Result<Direction> {
+    if mac_key.len() != suite.mac_len() || key.len() != KEY_LEN {
+      return Err(Error::InvalidParameter("wrong TLS key length"));
+    }
+
+    let mac = RecordMac::new(suite, mac_key);
+    let rc4 = Rc4::try_new(key)?;
+    Ok(Direction { suite, version, mac, rc4, seq: 0 })
+  }
+
+  /// Returns the cipher suite.
+  pub fn suite(&self) -> CipherSuite {
+    self.suite
+  }
+
+  /// Returns the sequence number of the next record.
+  pub fn sequence_number(&self) -> u64 {
+    self.seq
+  }
+
+  /// MACs and encrypts a fragment, returning the whole record.
+  pub fn protect(&mut self, content_type: ContentType, fragment: &[u8]) -> Result<Vec<u8>> {
+    if fragment.len() > MAX_FRAGMENT_LEN {
+      return Err(Error::InvalidParameter("TLS fragment is too long"));
+    }
+
+    let mac = self.mac.compute(&self.mac_header(content_type, fragment.len())?, fragment);
+    let len = fragment.len() + mac.len();
+
+    let mut record = Vec::with_capacity(HEADER_LEN + len);
+    record.push(content_type.to_u8());
+    record.extend_from_slice(&self.version.to_u16().to_be_bytes());
+    record.extend_from_slice(&(len as u16).to_be_bytes());
+    record.extend_from_slice(fragment);
+    record.extend_from_slice(&mac);
+
+    self.rc4.try_apply_keystream(&mut record[HEADER_LEN..])?;
+    self.seq += 1;
+    Ok(record)
+  }
+
+  /// Decrypts the record at the start of `data` and checks its MAC.
+  ///
+  /// Returns `None`, without touching the keystream, if `data` doesn't
+  /// hold the whole record yet. A failed MAC check leaves the keystream
+  /// out of step, so the connection must be closed as TLS requires.
+  pub fn unprotect(&mut self, data: &[u8]) -> Result<Option<Record>> {
+    if data.len() < HEADER_LEN {
+      return Ok(None);
+    }
+
+    let content_type = ContentType::from_u8(data[0])
+      .ok_or(Error::Format("unknown TLS content type"))?;
+    if u16::from_be_bytes([data[1], data[2]]) != self.version.to_u16() {
+      return Err(Error::Format("unexpected TLS record version"));
+    }
+
+    let len = usize::from(u16::from_be_bytes([data[3], data[4]]));
+    let mac_len = self.suite.mac_len();
+    if len > MAX_CIPHERTEXT_LEN {
+      return Err(Error::Format("TLS record is too long"));
+    }
+    if len < mac_len {
+      return Err(Error::Format("TLS record is too short"));
+    }
+
+    if data.len() < HEADER_LEN + len {
+      return Ok(None);
+    }
+
+    let mut fragment = data[HEADER_LEN..HEADER_LEN + len].to_vec();
+    self.rc4.try_apply_keystream(&mut fragment)?;
+
+    let tag = fragment.split_off(len - mac_len);
+    let mac = self.mac.compute(&self.mac_header(content_type, fragment.len())?, &fragment);
+    self.seq += 1;
+
+    if !ct::eq(&mac, &tag) {
+      wipe::wipe(&mut fragment);
+      return Err(Error::AuthenticationFailed);
+    }
+
+    if fragment.len() > MAX_FRAGMENT_LEN {
+      return Err(Error::Format("TLS fragment is too long"));
+    }
+
+    Ok(Some(Record { content_type, fragment, len: HEADER_LEN + len }))
+  }
+
+  fn mac_header(&self, content_type: ContentType, len: usize) -> Result<[u8; 13]> {
+    if self.seq == u64::MAX {
+      return Err(Error::InvalidParameter("TLS sequence number exhausted"));
+    }
+
+    let mut header = [0u8; 13];
+    header[..8].copy_from_slice(&self.seq.to_be_bytes());
+    header[8] = content_type.to_u8();
+    header[9..11].copy_from_slice(&self.version.to_u16().to_be_bytes());
+    header[11..].copy_from_slice(&(len as u16).to_be_bytes());
+    Ok(header)
+  }
+}
+
+impl fmt::Debug for Direction {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Direction")
+      .field("suite", &self.suite)
+      .field("version", &self.version)
+      .finish_non_exhaustive()
+  }
+}
+
+/// Splits a key block into the write and read directions for `side`.
+///
+/// The key block is laid out as the client and server MAC keys followed
+/// by the client and server RC4 keys; any bytes after those are unused.
+pub fn from_key_block(
+  suite: CipherSuite,
+  version: Version,
+  side: Side,
+  key_block: &[u8]
+) -> Result<(Direction, Direction)> {
+  if key_block.len() < suite.key_block_len() {
+    return Err(Error::InvalidParameter("TLS key block is too short"));
+  }
+
+  let (macs, keys) = key_block.split_at(2 * suite.mac_len());
+  let (client_mac, server_mac) = macs.split_at(suite.mac_len());
+  let (client_key, server_key) = (&keys[..KEY_LEN], &keys[KEY_LEN..2 * KEY_LEN]);
+
+  let client = Direction::new(suite, version, client_mac, client_key)?;
+  let server = Direction::new(suite, version, server_mac, server_key)?;
+
+  Ok(match side {
+    Side::Client => (client, server),
+    Side::Server => (server, client)
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use crate::test_util::{from_hex, to_hex};
+  use crate::tls_record::{self, CipherSuite, ContentType, Direction, Record, Side, Version};
+  use crate::Error;
+
+  // These transcripts come from Python's `cryptography` ARC4 and
+  // `hmac`, following RFC 2246 and RFC 5246. The key block is
+  // 00 01 02 ... and the client sends a Finished message and then an
+  // HTTP request.
+  const SHA_TLS12: &str = "160303002411fe906e37322d4f819b9f657bedf1a82f5d32cce64776170483c6\
+                           1264902a4d30c9173c170303002612b227507d493a3c1a59959a892b54d59dd0\
+                           062937a6622f3ceaa4dd0c22ec057502eed9872b";
+  const MD5_TLS10: &str = "1603010020b89e73368ca486e1c3f4cfaff4149c6e45d419dba30a793af9373c\
+                           9062964a9d17030100223289451600c2a9a1e52df9204045bc9b5a9acce59aaa\
+                           3f3cef6275958cd10361fb73";
+
+  // The server's HTTP response under the SHA and TLS 1.2 key block.
+  const SHA_TLS12_SERVER: &str = "170303002711742712a24b0736506bd0d8451ac404e8d638819f57291c3\
+                                  1d84a4fb3b8c036b3087877315b3c";
+
+  // Sessions between OpenSSL 1.1.1w `s_client` and `s_server` built
+  // with enable-weak-ssl-ciphers, captured on the wire. The key blocks
+  // were derived from the master secrets in `-keylogfile`. After
+  // ChangeCipherSpec the client sends Finished, REQUEST and a
+  // close_notify alert; the server sends Finished and a response.
+  const OPENSSL: &[(CipherSuite, Version, &str, &str, &str)] = &[
+    (
+      CipherSuite::Rc4128Sha,
+      Version::Tls12,
+      "d606e0eebf0eedf6abaae287b470e8be942b7c10d94412e4d253fb0511e3a036\
+       9a49a7603ec1c4e4773304169a59e515f346134768aa998a7d142791a4a757b5\
+       e276cc0782216ed1",
+      "160303002496acf4b3a685dedcd1a12d7d4839fb36927d4d7fbd16c1ea456f11\
+       603cf5b7ee27211a6f1703030026061528e2349db5720ee0255261b547119dbf\
+       e2a6ff113514f4faf23d0c2cf1f0dcfaed522bf31503030016e97d57106ce18f\
+       98bef762f115a44c68c017f13dbded",
+      "1603030024705475950ab5f605698d57e0a80f08156aa23e373f2392a786bfd4\
+       97bcea4876d8f12feb1703030027f8ccaced3e28fea5df2b30738e5c632e222d\
+       417915303891b68ced162ce4ddc959835e6ab16d61"
+    ),
+    (
+      CipherSuite::Rc4128Md5,
+      Version::Tls10,
+      "72e1d829c205af49ebc87a857f10f805998fc8c935b19218844a01b7e50fab71\
+       ef29c19d52bee98c4a51bebe4e8c1fa9b9b0591471eafbc3fbeb1604d497bbde",
+      "16030100206fc3799295a8f170fa73a942c31c11ba59813ad6930ce98facea0b\
+       7f2042640017030100221c77047b28168532b3458623bcfbeca383df5645b532\
+       7e181dbdb0d5eeb1915125871503010012d78f626e01e37f469ef01a91db0d04\
+       1ecdef",
+      "16030100201e7d71f88c743ca7cb5cea78c3cbaa75f4d05b00f1a70a0ba51f53\
+       9afb975e8c1703010023382ef4eda816fe5235d9a1198ae686e18c003b90da21\
+       9ce32fe7546827b5074b0a773b"
+    )
+  ];
+
+  const FINISHED: &str = "1400000ca0a1a2a3a4a5a6a7a8a9aaab";
+  const REQUEST: &[u8] = b"GET / HTTP/1.0\r\n\r\n";
+
+  fn key_block(suite: CipherSuite) -> Vec<u8> {
+    (0..suite.key_block_len() as u8).collect()
+  }
+
+  #[test]
+  fn test_transcripts() {
+    for (suite, version, hex) in [
+      (CipherSuite::Rc4128Sha, Version::Tls12, SHA_TLS12),
+      (CipherSuite::Rc4128Md5, Version::Tls10, MD5_TLS10)
+    ] {
+      let key_block = key_block(suite);
+
+      let (mut client, _) =
+        tls_record::from_key_block(suite, version, Side::Client, &key_block).unwrap();
+      let mut wire = client.protect(ContentType::Handshake, &from_hex(FINISHED)).unwrap();
+      wire.extend(client.protect(ContentType::ApplicationData, REQUEST).unwrap());
+      assert_eq!(to_hex(&wire), hex);
+      assert_eq!(client.sequence_number(), 2);
+
+      let (_, mut server) =
+        tls_record::from_key_block(suite, version, Side::Server, &key_block).unwrap();
+      let first = server.unprotect(&wire).unwrap().unwrap();
+      assert_eq!(first.content_type(), ContentType::Handshake);
+      assert_eq!(to_hex(first.fragment()), FINISHED);
+
+      let second = server.unprotect(&wire[first.len()..]).unwrap().unwrap();
+      assert_eq!(second.content_type(), ContentType::ApplicationData);
+      assert_eq!(second.into_fragment(), REQUEST);
+    }
+  }
+
+  #[test]
+  fn test_server_direction() {
+    let suite = CipherSuite::Rc4128Sha;
+    let key_block = key_block(suite);
+    let response = b"HTTP/1.0 200 OK\r\n\r\n";
+
+    let (mut server, _) =
+      tls_record::from_key_block(suite, Version::Tls12, Side::Server, &key_block).unwrap();
+    let record = server.protect(ContentType::ApplicationData, response).unwrap();
+    assert_eq!(to_hex(&record), SHA_TLS12_SERVER);
+
+    let (_, mut client) =
+      tls_record::from_key_block(suite, Version::Tls12, Side::Client, &key_block).unwrap();
+    assert_eq!(client.unprotect(&record).unwrap().unwrap().fragment(), response);
+  }
+
+  // Unprotects every record in `wire` with `rx` and checks that `tx`
+  // protects the fragments back into the same bytes.
+  fn replay(tx: &mut Direction, rx: &mut Direction, wire: &[u8]) -> Vec<Record> {
+    let mut records = Vec::new();
+    let mut pos = 0;
+
+    while pos < wire.len() {
+      let record = rx.unprotect(&wire[pos..]).unwrap().unwrap();
+      let protected = tx.protect(record.content_type(), record.fragment()).unwrap();
+      assert_eq!(protected, &wire[pos..pos + record.len()]);
+      pos += record.len();
+      records.push(record);
+    }
+
+    records
+  }
+
+  #[test]
+  fn test_openssl() {
+    for &(suite, version, key_block, client_hex, server_hex) in OPENSSL {
+      let key_block = from_hex(key_block);
+      let (mut client_tx, mut client_rx) =
+        tls_record::from_key_block(suite, version, Side::Client, &key_block).unwrap();
+      let (mut server_tx, mut server_rx) =
+        tls_record::from_key_block(suite, version, Side::Server, &key_block).unwrap();
+
+      let records = replay(&mut client_tx, &mut server_rx, &from_hex(client_hex));
+      assert_eq!(records.len(), 3);
+      assert_eq!(records[0].content_type(), ContentType::Handshake);
+      assert_eq!(&records[0].fragment()[..4], &[20, 0, 0, 12]);
+      assert_eq!(records[1].fragment(), REQUEST);
+      assert_eq!(records[2].content_type(), ContentType::Alert);
+      assert_eq!(records[2].fragment(), &[1, 0]);
+
+      let records = replay(&mut server_tx, &mut client_rx, &from_hex(server_hex));
+      assert_eq!(records.len(), 2);
+      assert_eq!(&records[0].fragment()[..4], &[20, 0, 0, 12]);
+      assert_eq!(records[1].fragment(), b"HTTP/1.0 200 OK\r\n\r\n");
+    }
+  }
+
+  #[test]
+  fn test_partial_record() {
+    let suite = CipherSuite::Rc4128Md5;
+    let key_block = key_block(suite);
+    let (mut tx, _) =
+      tls_record::from_key_block(suite, Version::Tls11, Side::Client, &key_block).unwrap();
+    let (_, mut rx) =
+      tls_record::from_key_block(suite, Version::Tls11, Side::Server, &key_block).unwrap();
+
+    // Incomplete records leave the keystream where it was.
+    let record = tx.protect(ContentType::Alert, &[1, 0]).unwrap();
+    assert!(rx.unprotect(&record[..4]).unwrap().is_none());
+    assert!(rx.unprotect(&record[..record.len() - 1]).unwrap().is_none());
+    assert_eq!(rx.sequence_number(), 0);
+    assert_eq!(rx.unprotect(&record).unwrap().unwrap().fragment(), &[1, 0]);
+  }
+
+  #[test]
+  fn test_errors() {
+    let suite = CipherSuite::Rc4128Sha;
+    let key_block = key_block(suite);
+    let client = || tls_record::from_key_block(suite, Version::Tls12, Side::Client, &key_block);
+    let server = || tls_record::from_key_block(suite, Version::Tls12, Side::Server, &key_block);
+
+    assert!(tls_record::from_key_block(suite, Version::Tls12, Side::Client, &[0; 64]).is_err());
+    assert!(Direction::new(suite, Version::Tls12, &[0; 16], &[0; 16]).is_err());
+    assert_eq!(CipherSuite::from_id(0x0005), Some(suite));
+    assert_eq!(Version::from_u16(0x0300), None);
+
+    let (mut tx, _) = client().unwrap();
+    assert!(tx.protect(ContentType::ApplicationData, &[0; 1 << 14]).is_ok());
+    let result = tx.protect(ContentType::ApplicationData, &[0; (1 << 14) + 1]);
+    assert!(matches!(result, Err(Error::InvalidParameter(_))));
+
+    let (mut tx, _) = client().unwrap();
+    let mut record = tx.protect(ContentType::ApplicationData, b"data").unwrap();
+    let (_, mut rx) = server().unwrap();
+
+    let mut bad = record.clone();
+    bad[0] = 24;
+    assert!(matches!(rx.unprotect(&bad), Err(Error::Format(_))));
+    bad = record.clone();
+    bad[2] = 1;
+    assert!(matches!(rx.unprotect(&bad), Err(Error::Format(_))));
+    assert!(matches!(rx.unprotect(&[23, 3, 3, 0, 19]), Err(Error::Format(_))));
+
+    // A replayed record's MAC covers the wrong sequence number.
+    assert!(rx.unprotect(&record).unwrap().is_some());
+    assert!(matches!(rx.unprotect(&record), Err(Error::AuthenticationFailed)));
+
+    let (_, mut rx) = server().unwrap();
+    let last = record.len() - 1;
+    record[last] ^= 1;
+    assert!(matches!(rx.unprotect(&record), Err(Error::AuthenticationFailed)));
+
+    assert_eq!(format!("{:?}", tx), "Direction { suite: Rc4128Sha, version: Tls12, .. }");
+  }
+}
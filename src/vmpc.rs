@@ -0,0 +1,249 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The VMPC stream cipher by Bartosz Zoltak, "VMPC One-Way Function and
+//! Stream Cipher" (FSE 2004), and its VMPC-KSA3 key schedule.
+
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::{identity, key, wipe, Error, KeyError, KeystreamGenerator, Result};
+
+/// The VMPC keystream.
+///
+/// The permutation is zeroized on drop and left out of `Debug` output.
+#[derive(Clone)]
+pub struct Vmpc {
+  n: u8,
+  s: u8,
+  p: [u8; 256],
+  pos: u64
+}
+
+impl Vmpc {
+  /// Runs the VMPC key schedule over `key`, without an IV.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `key` is empty or longer than 256 bytes.
+  pub fn new(key: &[u8]) -> Vmpc {
+    match Vmpc::try_new(key) {
+      Ok(vmpc) => vmpc,
+      Err(e) => panic!("{}", e)
+    }
+  }
+
+  /// Runs the VMPC key schedule over `key`, without an IV, rejecting
+  /// keys that are empty or longer than 256 bytes.
+  pub fn try_new(key: &[u8]) -> Result<Vmpc> {
+    key::check(key)?;
+
+    let mut vmpc = Vmpc { n: 0, s: 0, p: identity(), pos: 0 };
+    vmpc.mix(key);
+    Ok(vmpc)
+  }
+
+  /// Runs the VMPC key schedule over `key`, then over `iv`.
+  pub fn with_iv(key: &[u8], iv: &[u8]) -> Result<Vmpc> {
+    key::check(key)?;
+    check_iv(iv)?;
+
+    let mut vmpc = Vmpc { n: 0, s: 0, p: identity(), pos: 0 };
+    vmpc.mix(key);
+    vmpc.mix(iv);
+    Ok(vmpc)
+  }
+
+  /// Runs the VMPC-KSA3 key schedule, which mixes `key` in once more
+  /// after `iv`.
+  pub fn with_iv_ksa3(key: &[u8], iv: &[u8]) -> Result<Vmpc> {
+    let mut vmpc = Vmpc::with_iv(key, iv)?;
+    vmpc.mix(key);
+    Ok(vmpc)
+  }
+
+  /// XORs the keystream into `buf` in place.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the keystream position would overflow a `u64`.
+  pub fn apply_keystream(&mut self, buf: &mut [u8]) {
+    if let Err(e) = self.try_apply_keystream(buf) {
+      panic!("{}", e);
+    }
+  }
+
+  /// XORs the keystream into `buf` in place, failing if the keystream
+  /// position would overflow a `u64`.
+  pub fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<()> {
+    self.pos = self.checked_pos(buf.len() as u64)?;
+
+    for b in buf.iter_mut() {
+      *b ^= self.next_byte();
+    }
+
+    Ok(())
+  }
+
+  /// Advances the keystream by `n` bytes without producing output.
+  pub fn skip(&mut self, n: u64) -> Result<()> {
+    self.pos = self.checked_pos(n)?;
+
+    for _ in 0..n {
+      self.next_byte();
+    }
+
+    Ok(())
+  }
+
+  /// Returns the number of keystream bytes consumed so far.
+  pub fn position(&self) -> u64 {
+    self.pos
+  }
+
+  // One 768-step pass of the key schedule. `s` carries over between
+  // passes and into keystream generation.
+  fn mix(&mut self, bytes: &[u8]) {
+    let p = &mut self.p;
+
+    for m in 0..768 {
+      let n = m % 256;
+      let t = self.s.wrapping_add(p[n]).wrapping_add(bytes[m % bytes.len()]);
+      self.s = p[usize::from(t)];
+      p.swap(n, usize::from(self.s));
+    }
+  }
+
+  fn checked_pos(&self, n: u64) -> Result<u64> {
+    self.pos.checked_add(n).ok_or(Error::KeystreamExhausted)
+  }
+
+  fn next_byte(&mut self) -> u8 {
+    let p = &mut self.p;
+    let n = usize::from(self.n);
+
+    self.s = p[usize::from(self.s.wrapping_add(p[n]))];
+    let s = usize::from(self.s);
+
+    let out = p[usize::from(p[usize::from(p[s])].wrapping_add(1))];
+    p.swap(n, s);
+    self.n = self.n.wrapping_add(1);
+
+    out
+  }
+}
+
+fn check_iv(iv: &[u8]) -> Result<()> {
+  match iv.len() {
+    0 => Err(KeyError::EmptyIv.into()),
+    len if len > key::MAX_KEY_LEN => Err(KeyError::IvTooLong { len }.into()),
+    _ => Ok(())
+  }
+}
+
+impl KeystreamGenerator for Vmpc {
+  fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<()> {
+    Vmpc::try_apply_keystream(self, buf)
+  }
+
+  fn skip(&mut self, n: u64) -> Result<()> {
+    Vmpc::skip(self, n)
+  }
+
+  fn position(&self) -> u64 {
+    self.pos
+  }
+}
+
+impl Drop for Vmpc {
+  fn drop(&mut self) {
+    wipe::wipe(&mut self.p);
+    wipe::wipe_value(&mut self.n);
+    wipe::wipe_value(&mut self.s);
+    wipe::wipe_value(&mut self.pos);
+  }
+}
+
+impl fmt::Debug for Vmpc {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Vmpc").finish_non_exhaustive()
+  }
+}
+
+impl Read for Vmpc {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    buf.fill(0);
+    self.try_apply_keystream(buf)?;
+    Ok(buf.len())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::test_util::from_hex;
+  use crate::{Error, KeyError, KeystreamReader, KeystreamWriter, Vmpc};
+  use std::io::{Read, Write};
+
+  const KEY: &str = "9661410AB797D8A9EB767C21172DF6C7";
+  const IV: &str = "4B5C2F003E67F39557A8D26F3DA2B155";
+
+  // Keystream positions sampled by the author's test vectors.
+  const POSITIONS: [u64; 16] = [
+    0, 1, 2, 3, 252, 253, 254, 255, 1020, 1021, 1022, 1023, 102396, 102397, 102398, 102399
+  ];
+
+  fn sample(mut vmpc: Vmpc) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for &pos in POSITIONS.iter() {
+      vmpc.skip(pos - vmpc.position()).unwrap();
+
+      let mut b = [0u8];
+      vmpc.apply_keystream(&mut b);
+      out.push(b[0]);
+    }
+
+    out
+  }
+
+  #[test]
+  fn test_vmpc() {
+    let vmpc = Vmpc::with_iv(&from_hex(KEY), &from_hex(IV)).unwrap();
+    assert_eq!(sample(vmpc), from_hex("A82479F5B8FC66A4E05640A581CA499A"));
+  }
+
+  #[test]
+  fn test_vmpc_ksa3() {
+    let vmpc = Vmpc::with_iv_ksa3(&from_hex(KEY), &from_hex(IV)).unwrap();
+    assert_eq!(sample(vmpc), from_hex("B6EBAEFE481724731DAEC35A1DA7E1DC"));
+  }
+
+  #[test]
+  fn test_bad_iv() {
+    assert!(matches!(Vmpc::with_iv(b"Key", b""), Err(Error::Key(KeyError::EmptyIv))));
+    assert!(matches!(
+      Vmpc::with_iv_ksa3(b"Key", &[0; 300]),
+      Err(Error::Key(KeyError::IvTooLong { len: 300 }))
+    ));
+    assert!(Vmpc::try_new(b"").is_err());
+  }
+
+  #[test]
+  fn test_data() {
+    let plain = b"Attack at dawn";
+    let key = from_hex(KEY);
+    let iv = from_hex(IV);
+
+    let vmpc = Vmpc::with_iv(&key, &iv).unwrap();
+    let mut writer = KeystreamWriter::from_keystream(vmpc, Vec::new());
+    writer.write_all(plain).unwrap();
+    let cipher = writer.into_inner();
+
+    let vmpc = Vmpc::with_iv(&key, &iv).unwrap();
+    let mut reader = KeystreamReader::from_keystream(vmpc, &cipher[..]);
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, plain);
+  }
+}
@@ -0,0 +1,222 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! WEP, the original IEEE 802.11 frame encryption.
+//!
+//! A protected frame body is a 3-byte IV, a byte holding the key ID in
+//! its top two bits, and the plaintext followed by its CRC-32 (the ICV),
+//! both encrypted with RC4 keyed with `IV || secret`.
+//!
+//! WEP is thoroughly broken and only here to talk to legacy equipment.
+//! The ICV is not a MAC; it only catches wrong keys and corruption.
+
+use std::fmt;
+
+use crate::{wipe, Error, Rc4, Result};
+
+/// The length of the per-frame IV.
+pub const IV_LEN: usize = 3;
+
+/// The length of the IV and key ID byte in front of the ciphertext.
+pub const HEADER_LEN: usize = IV_LEN + 1;
+
+/// The length of the encrypted CRC-32 after the data.
+pub const ICV_LEN: usize = 4;
+
+/// The number of key slots; key IDs range from 0 to 3.
+pub const KEY_SLOTS: usize = 4;
+
+/// A 40-bit or 104-bit WEP secret.
+///
+/// The key bytes are zeroized on drop and redacted from `Debug`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct WepKey(Vec<u8>);
+
+impl WepKey {
+  /// Accepts 5-byte (40-bit) and 13-byte (104-bit) secrets.
+  pub fn new(secret: &[u8]) -> Result<WepKey> {
+    match secret.len() {
+      5 | 13 => Ok(WepKey(secret.to_vec())),
+      _ => Err(Error::InvalidParameter("WEP keys are 5 or 13 bytes"))
+    }
+  }
+
+  /// Returns the secret's length in bits, 40 or 104.
+  pub fn bits(&self) -> usize {
+    self.0.len() * 8
+  }
+
+  fn keystream(&self, iv: &[u8]) -> Rc4 {
+    let mut seed = [0u8; IV_LEN + 13];
+    let len = IV_LEN + self.0.len();
+    seed[..IV_LEN].copy_from_slice(iv);
+    seed[IV_LEN..len].copy_from_slice(&self.0);
+
+    let rc4 = Rc4::new(&seed[..len]);
+    wipe::wipe(&mut seed);
+    rc4
+  }
+}
+
+impl Drop for WepKey {
+  fn drop(&mut self) {
+    wipe::wipe(&mut self.0);
+  }
+}
+
+impl fmt::Debug for WepKey {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("WepKey").finish_non_exhaustive()
+  }
+}
+
+/// The four default keys a station can select with the key ID.
+#[derive(Clone, Debug, Default)]
+pub struct KeySet {
+  keys: [Option<WepKey>; KEY_SLOTS]
+}
+
+impl KeySet {
+  /// Returns an empty key set.
+  pub fn new() -> KeySet {
+    KeySet::default()
+  }
+
+  /// Installs `key` in slot `id`.
+  pub fn set(&mut self, id: u8, key: WepKey) -> Result<()> {
+    self.keys[check_key_id(id)?] = Some(key);
+    Ok(())
+  }
+
+  /// Returns the key in slot `id`, if any.
+  pub fn get(&self, id: u8) -> Option<&WepKey> {
+    self.keys.get(usize::from(id))?.as_ref()
+  }
+
+  /// Decrypts a frame body with the key its key ID selects.
+  pub fn decapsulate(&self, body: &[u8]) -> Result<Vec<u8>> {
+    match self.get(key_id(body)?) {
+      Some(key) => decapsulate(key, body),
+      None => Err(Error::InvalidParameter("no WEP key for the frame's key ID"))
+    }
+  }
+}
+
+/// Encrypts `data` into a protected frame body.
+///
+/// The IV must differ for every frame; repeating one with the same key
+/// repeats the keystream.
+pub fn encapsulate(key: &WepKey, id: u8, iv: [u8; IV_LEN], data: &[u8]) -> Result<Vec<u8>> {
+  let id = check_key_id(id)? as u8;
+
+  let mut body = Vec::with_capacity(HEADER_LEN + data.len() + ICV_LEN);
+  body.extend_from_slice(&iv);
+  body.push(id << 6);
+  body.extend_from_slice(data);
+  body.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+
+  key.keystream(&iv).apply_keystream(&mut body[HEADER_LEN..]);
+  Ok(body)
+}
+
+/// Decrypts a protected frame body and checks its ICV.
+///
+/// The key ID in the body is ignored; `KeySet::decapsulate` uses it to
+/// pick the key.
+pub fn decapsulate(key: &WepKey, body: &[u8]) -> Result<Vec<u8>> {
+  if body.len() < HEADER_LEN + ICV_LEN {
+    return Err(Error::Format("WEP frame body is too short"));
+  }
+
+  let mut data = body[HEADER_LEN..].to_vec();
+  key.keystream(&body[..IV_LEN]).apply_keystream(&mut data);
+
+  let icv = data.split_off(data.len() - ICV_LEN);
+  if crc32fast::hash(&data).to_le_bytes() != icv[..] {
+    wipe::wipe(&mut data);
+    return Err(Error::AuthenticationFailed);
+  }
+
+  Ok(data)
+}
+
+/// Returns the key ID of a protected frame body.
+pub fn key_id(body: &[u8]) -> Result<u8> {
+  match body.get(IV_LEN) {
+    Some(b) => Ok(b >> 6),
+    None => Err(Error::Format("WEP frame body is too short"))
+  }
+}
+
+fn check_key_id(id: u8) -> Result<usize> {
+  match usize::from(id) {
+    id if id < KEY_SLOTS => Ok(id),
+    _ => Err(Error::InvalidParameter("WEP key IDs range from 0 to 3"))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::test_util::{from_hex, to_hex};
+  use crate::wep::{self, KeySet, WepKey};
+  use crate::Error;
+
+  #[test]
+  fn test_encapsulate() {
+    // Computed with Python's `cryptography` ARC4 and `zlib.crc32`.
+    let key = WepKey::new(&from_hex("0102030405")).unwrap();
+    let body = wep::encapsulate(&key, 2, [0x0a, 0x0b, 0x0c], b"hello WEP").unwrap();
+    assert_eq!(to_hex(&body), "0a0b0c808a80ba01d3990119ff0d33cc75");
+
+    assert_eq!(wep::key_id(&body).unwrap(), 2);
+    assert_eq!(wep::decapsulate(&key, &body).unwrap(), b"hello WEP");
+  }
+
+  #[test]
+  fn test_roundtrip() {
+    let key = WepKey::new(&from_hex("000102030405060708090a0b0c")).unwrap();
+    assert_eq!(key.bits(), 104);
+
+    for len in [0, 1, 100, 1500] {
+      let data = vec![0x5a; len];
+      let body = wep::encapsulate(&key, 0, [1, 2, 3], &data).unwrap();
+      assert_eq!(body.len(), len + 8);
+      assert_eq!(wep::decapsulate(&key, &body).unwrap(), data);
+    }
+  }
+
+  #[test]
+  fn test_key_set() {
+    let a = WepKey::new(b"aaaaa").unwrap();
+    let b = WepKey::new(b"bbbbbbbbbbbbb").unwrap();
+
+    let mut keys = KeySet::new();
+    keys.set(0, a.clone()).unwrap();
+    keys.set(3, b.clone()).unwrap();
+    assert!(keys.set(4, b.clone()).is_err());
+
+    let body = wep::encapsulate(&b, 3, [0; 3], b"msg").unwrap();
+    assert_eq!(keys.decapsulate(&body).unwrap(), b"msg");
+
+    let body = wep::encapsulate(&b, 1, [0; 3], b"msg").unwrap();
+    assert!(matches!(keys.decapsulate(&body), Err(Error::InvalidParameter(_))));
+  }
+
+  #[test]
+  fn test_errors() {
+    assert!(WepKey::new(b"").is_err());
+    assert!(WepKey::new(b"16 byte key.....").is_err());
+    assert_eq!(format!("{:?}", WepKey::new(b"aaaaa").unwrap()), "WepKey { .. }");
+
+    let key = WepKey::new(b"aaaaa").unwrap();
+    let mut body = wep::encapsulate(&key, 0, [0; 3], b"msg").unwrap();
+    assert!(matches!(wep::decapsulate(&key, &body[..7]), Err(Error::Format(_))));
+
+    let wrong = WepKey::new(b"bbbbb").unwrap();
+    assert!(matches!(wep::decapsulate(&wrong, &body), Err(Error::AuthenticationFailed)));
+
+    body[5] ^= 1;
+    assert!(matches!(wep::decapsulate(&key, &body), Err(Error::AuthenticationFailed)));
+  }
+}
@@ -0,0 +1,42 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::ptr;
+use std::sync::atomic::{compiler_fence, Ordering};
+
+/// Overwrites `buf` with zeros.
+///
+/// The writes are volatile and fenced so that the compiler can't
+/// elide them, even though `buf` is usually about to be freed.
+pub(crate) fn wipe(buf: &mut [u8]) {
+  for b in buf.iter_mut() {
+    // SAFETY: `b` is a valid, aligned and exclusive reference.
+    unsafe { ptr::write_volatile(b, 0) };
+  }
+
+  compiler_fence(Ordering::SeqCst);
+}
+
+/// Overwrites a single value with its zero value.
+pub(crate) fn wipe_value<T: Copy + Default>(x: &mut T) {
+  // SAFETY: `x` is a valid, aligned and exclusive reference.
+  unsafe { ptr::write_volatile(x, T::default()) };
+  compiler_fence(Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod test {
+  use crate::wipe::{wipe, wipe_value};
+
+  #[test]
+  fn test_wipe() {
+    let mut buf = [0xAAu8; 300];
+    wipe(&mut buf);
+    assert!(buf.iter().all(|&b| b == 0));
+
+    let mut x = 0xAAu8;
+    wipe_value(&mut x);
+    assert_eq!(x, 0);
+  }
+}
@@ -0,0 +1,152 @@
+#!/usr/bin/env python3
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+# Writes small PDFs encrypted with the Standard Security Handler,
+# revisions 2 to 4, following algorithms 1 to 5 of ISO 32000-1 7.6.3.
+# Independent of this crate; RC4 is implemented below so that any key
+# length works, and checked against the `cryptography` package.
+
+import hashlib
+import struct
+
+from cryptography.hazmat.decrepit.ciphers.algorithms import ARC4
+from cryptography.hazmat.primitives.ciphers import Cipher
+
+PAD = bytes.fromhex(
+    "28bf4e5e4e758a4164004e56fffa01082e2e00b6d0683e802f0ca9fe6453697a")
+
+ID0 = bytes.fromhex("5a1f0e3c7d9b2a4468e0c1f2a3b4c5d6")
+ID1 = bytes.fromhex("00112233445566778899aabbccddeeff")
+
+TITLE = b"Encrypted sample"
+CONTENT = b"BT /F1 12 Tf 20 100 Td (Hello, RC4!) Tj ET"
+
+
+def rc4(key, data):
+    s = list(range(256))
+    j = 0
+    for i in range(256):
+        j = (j + s[i] + key[i % len(key)]) & 0xff
+        s[i], s[j] = s[j], s[i]
+    out = bytearray()
+    i = j = 0
+    for b in data:
+        i = (i + 1) & 0xff
+        j = (j + s[i]) & 0xff
+        s[i], s[j] = s[j], s[i]
+        out.append(b ^ s[(s[i] + s[j]) & 0xff])
+    return bytes(out)
+
+
+assert rc4(b"k" * 16, CONTENT) == \
+    Cipher(ARC4(b"k" * 16), mode=None).encryptor().update(CONTENT)
+
+
+def md5(data):
+    return hashlib.md5(data).digest()
+
+
+def pad(password):
+    return (password + PAD)[:32]
+
+
+def xor_key(key, i):
+    return bytes(b ^ i for b in key)
+
+
+# Algorithm 3: the /O entry.
+def owner_entry(r, n, owner, user):
+    h = md5(pad(owner or user))
+    if r >= 3:
+        for _ in range(50):
+            h = md5(h)
+    key = h[:n]
+    o = rc4(key, pad(user))
+    if r >= 3:
+        for i in range(1, 20):
+            o = rc4(xor_key(key, i), o)
+    return o
+
+
+# Algorithm 2: the file encryption key.
+def file_key(r, n, user, o, p, meta):
+    h = md5(pad(user) + o + struct.pack("<i", p) + ID0 +
+            (b"\xff\xff\xff\xff" if r >= 4 and not meta else b""))
+    if r >= 3:
+        for _ in range(50):
+            h = md5(h[:n])
+    return h[:n]
+
+
+# Algorithms 4 and 5: the /U entry.
+def user_entry(r, key):
+    if r == 2:
+        return rc4(key, PAD)
+    u = rc4(key, md5(PAD + ID0))
+    for i in range(1, 20):
+        u = rc4(xor_key(key, i), u)
+    return u + bytes(16)
+
+
+# Algorithm 1: the per-object key.
+def object_key(key, num, gen):
+    h = md5(key + struct.pack("<I", num)[:3] + struct.pack("<H", gen))
+    return h[:min(len(key) + 5, 16)]
+
+
+def write(name, r, bits, user, owner, p, meta=True):
+    n = 5 if r == 2 else bits // 8
+    o = owner_entry(r, n, owner, user)
+    key = file_key(r, n, user, o, p, meta)
+    u = user_entry(r, key)
+
+    content = rc4(object_key(key, 4, 0), CONTENT)
+    title = rc4(object_key(key, 5, 0), TITLE)
+
+    if r == 2:
+        crypt = b"/V 1"
+    elif r == 3:
+        crypt = b"/V 2 /Length %d" % bits
+    else:
+        crypt = (b"/V 4 /Length %d /CF << /StdCF << /CFM /V2 /Length %d "
+                 b"/AuthEvent /DocOpen >> >> /StmF /StdCF /StrF /StdCF"
+                 % (bits, n))
+        if not meta:
+            crypt += b" /EncryptMetadata false"
+
+    objects = [
+        b"<< /Type /Catalog /Pages 2 0 R >>",
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>",
+        b"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] "
+        b"/Resources << /Font << /F1 7 0 R >> >> /Contents 4 0 R >>",
+        b"<< /Length %d >>\nstream\n%s\nendstream" % (len(content), content),
+        b"<< /Title <%s> >>" % title.hex().encode(),
+        b"<< /Filter /Standard %s /R %d /O <%s> /U <%s> /P %d >>"
+        % (crypt, r, o.hex().encode(), u.hex().encode(), p),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>",
+    ]
+
+    pdf = bytearray(b"%PDF-1.5\n%\xe2\xe3\xcf\xd3\n")
+    offsets = []
+    for i, body in enumerate(objects, 1):
+        offsets.append(len(pdf))
+        pdf += b"%d 0 obj\n%s\nendobj\n" % (i, body)
+
+    xref = len(pdf)
+    pdf += b"xref\n0 %d\n0000000000 65535 f \n" % (len(objects) + 1)
+    for off in offsets:
+        pdf += b"%010d 00000 n \n" % off
+    pdf += (b"trailer\n<< /Size %d /Root 1 0 R /Info 5 0 R /Encrypt 6 0 R "
+            b"/ID [<%s> <%s>] >>\nstartxref\n%d\n%%%%EOF\n"
+            % (len(objects) + 1, ID0.hex().encode(), ID1.hex().encode(), xref))
+
+    with open(name, "wb") as f:
+        f.write(pdf)
+
+
+write("rev2-40.pdf", 2, 40, b"user", b"owner", -44)
+write("rev3-128.pdf", 3, 128, b"", b"owner secret", -3904)
+write("rev3-56.pdf", 3, 56, b"pw", b"", -4)
+write("rev4-128.pdf", 4, 128, b"user4", b"owner4", -1028, meta=False)
@@ -0,0 +1,94 @@
+#!/usr/bin/env python3
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+# Writes WEP capture fixtures and the decrypted captures the pcap
+# decryptor is expected to produce. Uses the ARC4 implementation from
+# the `cryptography` package, independent of this crate.
+
+import struct
+import zlib
+
+from cryptography.hazmat.decrepit.ciphers.algorithms import ARC4
+from cryptography.hazmat.primitives.ciphers import Cipher
+
+KEY40 = bytes.fromhex("0102030405")
+KEY104 = bytes.fromhex("000102030405060708090a0b0c")
+
+LLC = bytes.fromhex("aaaa03000000")
+AP = bytes.fromhex("001122334455")
+STA = bytes.fromhex("00aabbccddee")
+DST = bytes.fromhex("ffffffffffff")
+
+
+def rc4(key, data):
+    return Cipher(ARC4(key), mode=None).encryptor().update(data)
+
+
+def wep(key, key_id, iv, body, corrupt=False):
+    icv = struct.pack("<I", zlib.crc32(body))
+    if corrupt:
+        icv = bytes([icv[0] ^ 1]) + icv[1:]
+    return iv + bytes([key_id << 6]) + rc4(iv + key, body + icv)
+
+
+def header(fc0, fc1, qos=False):
+    h = bytes([fc0, fc1]) + b"\x00\x00" + AP + STA + DST + b"\x10\x00"
+    return h + (b"\x00\x00" if qos else b"")
+
+
+def radiotap(frame):
+    # TSFT, flags (FCS at end), rate, channel.
+    rt = struct.pack("<BBHI", 0, 0, 22, 0x0F)
+    rt += struct.pack("<QBBHH", 0x0102030405060708, 0x10, 0x02, 2437, 0x00A0)
+    return rt + frame + struct.pack("<I", zlib.crc32(frame))
+
+
+def pcap(linktype, frames):
+    out = struct.pack("<IHHiIII", 0xA1B2C3D4, 2, 4, 0, 0, 65535, linktype)
+    for n, frame in enumerate(frames):
+        out += struct.pack("<IIII", 1700000000 + n, 1000 * n, len(frame), len(frame))
+        out += frame
+    return out
+
+
+def data(n):
+    payload = LLC + b"\x08\x00" + ("payload of frame %d " % n).encode() * (n + 1)
+    return payload
+
+
+def write(name, linktype, pairs):
+    with open(name + ".pcap", "wb") as f:
+        f.write(pcap(linktype, [p[0] for p in pairs]))
+    with open(name + "-decrypted.pcap", "wb") as f:
+        f.write(pcap(linktype, [p[1] for p in pairs]))
+
+
+beacon = header(0x80, 0x00) + bytes(12) + b"\x00\x04test"
+
+# Radiotap capture with a 40-bit key in slot 0.
+frames = []
+for n, (fc0, qos) in enumerate([(0x08, False), (0x88, True), (0x08, False)]):
+    body = data(n)
+    enc = header(fc0, 0x41, qos) + wep(KEY40, 0, bytes([n, 0x20, 0x30]), body)
+    dec = header(fc0, 0x01, qos) + body
+    frames.append((radiotap(enc), radiotap(dec)))
+
+frames.insert(1, (radiotap(beacon), radiotap(beacon)))
+
+# A frame for a key ID we don't have and one with a bad ICV stay as they are.
+other = radiotap(header(0x08, 0x41) + wep(KEY40, 2, b"\x09\x09\x09", data(7)))
+bad = radiotap(header(0x08, 0x41) + wep(KEY40, 0, b"\x0a\x0a\x0a", data(8), corrupt=True))
+frames += [(other, other), (bad, bad)]
+write("wep40-radiotap", 127, frames)
+
+# Plain 802.11 capture with a 104-bit key in slot 1, one frame WDS.
+frames = []
+for n, fc1 in enumerate([0x41, 0x43]):
+    body = data(n)
+    addr4 = AP if fc1 & 3 == 3 else b""
+    enc = header(0x08, fc1) + addr4 + wep(KEY104, 1, bytes([0xFE, n, 0x01]), body)
+    dec = header(0x08, fc1 & ~0x40) + addr4 + body
+    frames.append((enc, dec))
+write("wep104", 105, frames)